@@ -3,6 +3,7 @@ use gtk::prelude::*;
 use mdl::Cache;
 use mdl::Model;
 use mdl::Signal;
+use mdl::Signaler;
 use mdl::SignalerSync;
 use mdl::SigType;
 use mdl::Continue;
@@ -55,7 +56,7 @@ impl St {
         let sig = SignalerSync::new();
         St { cache, sig }
     }
-    pub fn c(&self) -> MutexGuard<Cache> {
+    pub fn c(&self) -> MutexGuard<'_, Cache> {
         self.cache.lock().unwrap()
     }
 }
@@ -100,12 +101,12 @@ fn main() {
     let entry = gtk::Entry::new();
     bx.pack_start(&entry, false, false, 0);
 
-    let scroll = gtk::ScrolledWindow::new(None, None);
+    let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
     let listbox = gtk::ListBox::new();
     scroll.add(&listbox);
     bx.pack_start(&scroll, true, true, 0);
 
-    let label = gtk::Label::new(&t.text[..]);
+    let label = gtk::Label::new(Some(&t.text[..]));
     bx.add(&label);
 
     window.add(&bx);
@@ -129,21 +130,19 @@ fn main() {
     // Connecting all events
     let st1 = st.clone();
     entry.connect_activate(move |e| {
-        e.get_text()
-         .map(|t| {
-            let c = &*st1.c();
-            let n = TodoApp::get(c, "app").map(|a| a.number).unwrap_or_default();
-            let r = TodoRow{ index: n, text: t, done: false };
-            let _ = r.store_sig(c, &st1.sig);
-            println!("STORED");
-         });
+        let t = e.get_text().to_string();
+        let c = &*st1.c();
+        let n = TodoApp::get(c, "app").map(|a| a.number).unwrap_or_default();
+        let r = TodoRow{ index: n, text: t, done: false };
+        let _ = r.store_sig(c, &st1.sig);
+        println!("STORED");
         e.set_text("");
     });
 
     let l = listbox.clone();
     let st1 = st.clone();
     st.sig.subscribe("todo", Box::new(
-        move |Signal{type_: t, name: n}| {
+        move |Signal{type_: t, name: n, ..}| {
             let c = &*st1.c();
             let s = &st1.sig;
             match t {
@@ -164,7 +163,7 @@ fn main() {
                 }
             };
         }
-    )).unwrap();
+    )).unwrap().detach();
 
     let st1 = st.clone();
     st.sig.subscribe("app", Box::new(
@@ -176,16 +175,18 @@ fn main() {
                     label.set_text(&msg[..]);
                 }).unwrap();
         }
-    )).unwrap();
+    )).unwrap().detach();
 
-    // signal loop
+    // signal loop, signals are dispatched when the signaler fd is readable
     let st1 = st.clone();
-    gtk::timeout_add(50, move || {
-        gtk::Continue(st1.sig.signal_loop_sync())
+    let fd = st.sig.fd().expect("can't create the signaler fd");
+    glib::source::unix_fd_add_local(fd, glib::IOCondition::IN, move |_, _| {
+        st1.sig.drain();
+        glib::Continue(st1.sig.is_running())
     });
 
     let index = Arc::new(Mutex::new(0));
-    gtk::timeout_add(1000, move || {
+    glib::timeout_add_local(1000, move || {
         let c = &*st.c();
         let _ = TodoApp::get(c, "app")
             .map(|ref mut a| {
@@ -194,7 +195,7 @@ fn main() {
                 a.text = TEXTS[idx].to_string();
                 a.store_sig(c, &st.sig)
             });
-        gtk::Continue(true)
+        glib::Continue(true)
     });
 
     gtk::main();
@@ -202,7 +203,7 @@ fn main() {
 
 fn add_row(list: &gtk::ListBox, row: &TodoRow, st: &St) {
     let w = gtk::Box::new(gtk::Orientation::Horizontal, 3);
-    let l = gtk::Label::new(&row.text[..]);
+    let l = gtk::Label::new(Some(&row.text[..]));
     let done = gtk::Button::new();
     let rm = gtk::Button::new();
 
@@ -234,6 +235,7 @@ fn add_row(list: &gtk::ListBox, row: &TodoRow, st: &St) {
     });
 
     let b = w.clone();
+    let lb = list.clone();
     let key = row.key();
     let st = st.clone();
     rm.connect_clicked(move |_| {
@@ -243,7 +245,10 @@ fn add_row(list: &gtk::ListBox, row: &TodoRow, st: &St) {
                 r.delete_sig(c, &st.sig)
             })
             .map(|_| {
-                b.get_parent().map(|p| p.destroy());
+                // removes the ListBoxRow
+                if let Some(p) = b.get_parent() {
+                    lb.remove(&p);
+                }
             });
     });
 
@@ -0,0 +1,99 @@
+use anyhow::Error;
+
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::store::Store;
+use crate::store::Continue;
+
+/// Future returned by the async store operations
+pub type AsyncResult<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>;
+
+/// Async version of the `Store` trait, to use stores from async code.
+///
+/// The store operations are blocking, so each operation runs in the tokio
+/// blocking pool and the async task is not blocked. This trait is
+/// implemented for all stores that can be shared between threads, like
+/// `BCache` or an `Arc<Cache>`.
+///
+/// This trait is only available with the `async` feature.
+pub trait AsyncStore {
+    /// Stores the value in the database with the corresponding key
+    fn push_async(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> AsyncResult<()>;
+
+    /// Retrieves the value in the database with the corresponding key
+    /// Returns an error if the key doesn't exists
+    fn pull_async<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> AsyncResult<T>
+        where F: Fn(&[u8]) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static;
+
+    /// Iterates over all objects that starts with the prefix and run
+    /// the function f. If f returns Continue(false) the iteration stops.
+    /// The function is called in the blocking pool thread
+    fn iter_async<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> AsyncResult<()>
+        where F: Fn(&[u8]) -> Continue + Send + 'static;
+
+    /// Retrieves all items in the database that starts with the prefix key
+    fn all_async<F, T>(&self, db: &'static str, prefix: &str, formatter: F)
+        -> AsyncResult<Vec<T>>
+        where F: Fn(&[u8]) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static;
+
+    /// Remove the corresponding data in the database by key
+    fn rm_async(&self, db: &'static str, key: &str)
+        -> AsyncResult<()>;
+}
+
+impl<S> AsyncStore for S
+    where S: Store + Clone + Send + Sync + 'static {
+
+    fn push_async(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> AsyncResult<()> {
+        let store = self.clone();
+        let key = key.to_string();
+        Box::pin(blocking(move || store.push(db, &key, value)))
+    }
+
+    fn pull_async<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> AsyncResult<T>
+        where F: Fn(&[u8]) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static {
+        let store = self.clone();
+        let key = key.to_string();
+        Box::pin(blocking(move || store.pull(db, &key, formatter)))
+    }
+
+    fn iter_async<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> AsyncResult<()>
+        where F: Fn(&[u8]) -> Continue + Send + 'static {
+        let store = self.clone();
+        let prefix = prefix.to_string();
+        Box::pin(blocking(move || store.iter(db, &prefix, f)))
+    }
+
+    fn all_async<F, T>(&self, db: &'static str, prefix: &str, formatter: F)
+        -> AsyncResult<Vec<T>>
+        where F: Fn(&[u8]) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static {
+        let store = self.clone();
+        let prefix = prefix.to_string();
+        Box::pin(blocking(move || store.all(db, &prefix, formatter)))
+    }
+
+    fn rm_async(&self, db: &'static str, key: &str)
+        -> AsyncResult<()> {
+        let store = self.clone();
+        let key = key.to_string();
+        Box::pin(blocking(move || store.rm(db, &key)))
+    }
+}
+
+/// Runs the blocking operation in the tokio blocking pool
+async fn blocking<F, T>(op: F) -> Result<T, Error>
+    where F: FnOnce() -> Result<T, Error> + Send + 'static,
+          T: Send + 'static {
+    tokio::task::spawn_blocking(op).await?
+}
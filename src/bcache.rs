@@ -1,42 +1,440 @@
 use anyhow::Error;
 use anyhow::anyhow;
 
-use std::collections::BTreeMap;
-use std::ops::Bound::{Included, Unbounded};
+use bincode::{serialize, deserialize};
+use crc32fast::hash as crc32;
 
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::path::Path;
+use std::process;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use crate::store::Store;
 use crate::store::Continue;
+use crate::signal::Signaler;
+use crate::signal::Signal;
+use crate::signal::SigType;
 
 /// BTreeMap cache. This struct implements the Store trait so it can be used
 /// to cache Model structs
 /// A BTreeMap is used to store the data in memory. This struct implements clone
 /// so it can be shared between threads safely creating a clone
+///
+/// The cache grows without limit, use `Cache::with_limits` to evict entries
+/// when the cache is full.
 #[derive(Clone)]
 pub struct Cache {
     db: Arc<RwLock<BTreeMap<String, Vec<u8>> >>,
+    evictor: Arc<Evictor>,
+    /// number of modifications, to autosave only when something changes
+    changes: Arc<AtomicU64>,
+}
+
+/// Snapshot file header: magic, format version, payload crc32 and length
+const SNAPSHOT_MAGIC: &[u8; 4] = b"MDLB";
+const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_HEADER: usize = 20;
+
+/// Unique temporary file names for concurrent saves to the same path
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Saves the cache periodically, in a thread. The thread saves a last time
+/// and stops when the autosave drops
+pub struct Autosave {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Which entry is evicted when the cache is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// least recently used, stored or pulled
+    Lru,
+    /// least frequently used, the least recently used for the same count
+    Lfu,
+}
+
+/// Cache size limits, `None` means no limit
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// max number of entries
+    pub entries: Option<usize>,
+    /// max size of the keys and values, in bytes
+    pub bytes: Option<usize>,
+    /// max number of entries of each db
+    pub db_entries: HashMap<&'static str, usize>,
+    pub policy: Policy,
+}
+
+/// Eviction statistics, returned by `Cache::stats`. Only caches with
+/// limits keep track of the entries
+#[derive(Clone, Debug, Default)]
+pub struct EvictionStats {
+    /// current number of entries
+    pub entries: usize,
+    /// current size of the keys and values
+    pub bytes: usize,
+    /// number of evicted entries
+    pub evictions: u64,
+    /// size of the evicted entries
+    pub evicted_bytes: u64,
+}
+
+type EvictHook = Box<dyn Fn(Signal) + Send + 'static>;
+
+/// (rank, full key) of the entries of one db, the first one is evicted first
+type Order = BTreeSet<((u64, u64), String)>;
+
+/// Tracks the entries usage to evict them
+#[derive(Default)]
+struct Evictor {
+    limits: Option<Limits>,
+    usage: Mutex<Usage>,
+    on_evict: Mutex<Option<EvictHook>>,
+}
+
+#[derive(Default)]
+struct Usage {
+    tick: u64,
+    /// full key -> entry usage
+    entries: HashMap<String, Entry>,
+    order: HashMap<&'static str, Order>,
+    stats: EvictionStats,
+}
+
+struct Entry {
+    db: &'static str,
+    size: usize,
+    rank: (u64, u64),
+    hits: u64,
 }
 
 impl Cache {
     pub fn new() -> Result<Cache, Error> {
         Ok(Cache {
             db: Arc::new(RwLock::new(BTreeMap::new())),
+            evictor: Arc::new(Evictor::default()),
+            changes: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Creates a cache that evicts entries when the `limits` are reached
+    pub fn with_limits(limits: Limits) -> Result<Cache, Error> {
+        Ok(Cache {
+            db: Arc::new(RwLock::new(BTreeMap::new())),
+            evictor: Arc::new(Evictor { limits: Some(limits), ..Evictor::default() }),
+            changes: Arc::new(AtomicU64::new(0)),
         })
     }
+
+    /// Creates a cache with the content of a snapshot file written with
+    /// `save_to`. Fails if the file is corrupted or from other version.
+    ///
+    /// The snapshot only has the entries, the limits of the saved cache are
+    /// not stored and the loaded cache has no limits
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Cache, Error> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        if data.len() < SNAPSHOT_HEADER || &data[0..4] != SNAPSHOT_MAGIC {
+            return Err(anyhow!("{} is not a cache snapshot", path.display()));
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let version = u32_at(4);
+        if version != SNAPSHOT_VERSION {
+            return Err(anyhow!("unsupported snapshot version {}, {}", version, path.display()));
+        }
+        let len = u64::from_le_bytes([data[12], data[13], data[14], data[15],
+                                      data[16], data[17], data[18], data[19]]);
+        let payload = &data[SNAPSHOT_HEADER..];
+        if payload.len() as u64 != len || crc32(payload) != u32_at(8) {
+            return Err(anyhow!("corrupted snapshot {}", path.display()));
+        }
+
+        let map: BTreeMap<String, Vec<u8>> = deserialize(payload)?;
+        let cache = Cache::new()?;
+        *cache.db.write().map_err(|_| anyhow!("DB ERROR"))? = map;
+        Ok(cache)
+    }
+
+    /// Writes the cache content to a snapshot file. The snapshot is written
+    /// to a temporary file that replaces the `path` file, so the file is
+    /// never half written
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let payload = match self.db.read() {
+            Ok(map) => serialize(&*map)?,
+            Err(_err) => return Err(anyhow!("DB ERROR")),
+        };
+
+        let name = path.file_name()
+            .ok_or_else(|| anyhow!("invalid snapshot path {}", path.display()))?
+            .to_string_lossy();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        // unique name, other threads or processes can save to the same path
+        let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp = dir.join(format!(".{}.{}-{}.tmp", name, process::id(), n));
+
+        let write = || -> Result<(), Error> {
+            let mut file = File::create(&tmp)?;
+            file.write_all(SNAPSHOT_MAGIC)?;
+            file.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+            file.write_all(&crc32(&payload).to_le_bytes())?;
+            file.write_all(&(payload.len() as u64).to_le_bytes())?;
+            file.write_all(&payload)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        // the rename is durable once the directory is synced
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Starts a thread that saves the cache to the `path` each `interval`,
+    /// if it's modified
+    pub fn autosave<P: AsRef<Path>>(&self, path: P, interval: Duration)
+        -> Result<Autosave, Error> {
+        let cache = self.clone();
+        let path = path.as_ref().to_path_buf();
+        let (stop, rx) = channel::<()>();
+        let thread = thread::Builder::new()
+            .name("mdl-autosave".to_string())
+            .spawn(move || {
+                let mut saved = None;
+                loop {
+                    let stopped = !matches!(rx.recv_timeout(interval), Err(RecvTimeoutError::Timeout));
+                    let changes = cache.changes.load(Ordering::SeqCst);
+                    if saved != Some(changes) && cache.save_to(&path).is_ok() {
+                        saved = Some(changes);
+                    }
+                    if stopped {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Autosave { stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// Emits a `Delete` signal for each evicted entry, with the evicted
+    /// value as the `old` value
+    pub fn evict_signals<G>(&self, sig: G)
+        where G: Signaler + Send + 'static {
+        let hook: EvictHook = Box::new(move |signal| { let _ = sig.emit_signal(signal); });
+        *self.evictor.on_evict.lock().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+    }
+
+    /// Returns the cache size and the eviction statistics
+    pub fn stats(&self) -> EvictionStats {
+        self.evictor.usage().stats.clone()
+    }
+}
+
+impl Autosave {
+    /// Stops the autosave thread, saving a last time, and waits for it
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+impl Limits {
+    /// Limits with only the max number of entries
+    pub fn entries(entries: usize, policy: Policy) -> Limits {
+        Limits { entries: Some(entries), ..Limits::new(policy) }
+    }
+
+    /// Limits with only the max size in bytes
+    pub fn bytes(bytes: usize, policy: Policy) -> Limits {
+        Limits { bytes: Some(bytes), ..Limits::new(policy) }
+    }
+
+    /// Without limits, to add the limits later
+    pub fn new(policy: Policy) -> Limits {
+        Limits { entries: None, bytes: None, db_entries: HashMap::new(), policy }
+    }
+
+    /// Sets the max number of entries of the `db`
+    pub fn db(mut self, db: &'static str, entries: usize) -> Limits {
+        self.db_entries.insert(db, entries);
+        self
+    }
+}
+
+impl Evictor {
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Updates the entry usage after a push and returns the keys to evict
+    fn pushed(&self, db: &'static str, key: String, size: usize)
+        -> Vec<(&'static str, String)> {
+        let limits = match self.limits {
+            Some(ref l) => l,
+            None => return vec![],
+        };
+
+        let mut usage = self.usage();
+        // overwriting a value is one more use, the hits are kept
+        let hits = usage.remove(&key).map(|e| e.hits + 1).unwrap_or(1);
+        usage.insert(db, key.clone(), size, hits, limits.policy);
+
+        let mut evicted = vec![];
+        if let Some(max) = limits.db_entries.get(db) {
+            while usage.order.get(db).map(|o| o.len()).unwrap_or(0) > *max {
+                let k = usage.first(Some(db), &key);
+                evicted.extend(k.and_then(|k| usage.evict(&k)));
+            }
+        }
+        loop {
+            let full = limits.entries.map(|m| usage.entries.len() > m).unwrap_or(false) ||
+                       limits.bytes.map(|m| usage.stats.bytes > m).unwrap_or(false);
+            if !full {
+                break;
+            }
+            match usage.first(None, &key).and_then(|k| usage.evict(&k)) {
+                Some(k) => evicted.push(k),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Updates the entry usage after a pull, `iter` doesn't count as a use
+    fn used(&self, key: &str) {
+        if let Some(ref limits) = self.limits {
+            self.usage().touch(key, limits.policy);
+        }
+    }
+
+    fn removed(&self, key: &str) {
+        if self.limits.is_some() {
+            self.usage().remove(key);
+        }
+    }
+
+    fn emit(&self, db: &'static str, key: &str, value: Vec<u8>) {
+        if let Some(ref f) = *self.on_evict.lock().unwrap_or_else(|e| e.into_inner()) {
+            let mut signal = Signal::new(SigType::Delete, &key[db.len() + 1..]);
+            signal.db = Some(db.to_string());
+            signal.old = Some(value);
+            f(signal);
+        }
+    }
+}
+
+impl Usage {
+    fn rank(&mut self, hits: u64, policy: Policy) -> (u64, u64) {
+        self.tick += 1;
+        match policy {
+            Policy::Lru => (self.tick, 0),
+            Policy::Lfu => (hits, self.tick),
+        }
+    }
+
+    fn insert(&mut self, db: &'static str, key: String, size: usize, hits: u64, policy: Policy) {
+        let rank = self.rank(hits, policy);
+        self.order.entry(db).or_default().insert((rank, key.clone()));
+        self.entries.insert(key, Entry { db, size, rank, hits });
+        self.stats.entries += 1;
+        self.stats.bytes += size;
+    }
+
+    fn touch(&mut self, key: &str, policy: Policy) {
+        let (db, old, hits) = match self.entries.get(key) {
+            Some(e) => (e.db, e.rank, e.hits + 1),
+            None => return,
+        };
+        let rank = self.rank(hits, policy);
+        if let Some(order) = self.order.get_mut(db) {
+            order.remove(&(old, key.to_string()));
+            order.insert((rank, key.to_string()));
+        }
+        if let Some(e) = self.entries.get_mut(key) {
+            e.rank = rank;
+            e.hits = hits;
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        if let Some(order) = self.order.get_mut(entry.db) {
+            order.remove(&(entry.rank, key.to_string()));
+        }
+        self.stats.entries -= 1;
+        self.stats.bytes -= entry.size;
+        Some(entry)
+    }
+
+    /// The next entry to evict, in the `db` or in all dbs. The just pushed
+    /// `key` is evicted only if there's nothing else, so new entries are not
+    /// evicted right away with the LFU policy
+    fn first(&self, db: Option<&'static str>, key: &str) -> Option<String> {
+        let firsts = self.order.iter()
+            .filter(|(d, _)| db.map(|db| db == **d).unwrap_or(true))
+            .filter_map(|(_, o)| o.iter().find(|(_, k)| k != key));
+        firsts.min().map(|(_, k)| k.clone())
+            .or_else(|| self.entries.get(key).map(|_| key.to_string()))
+    }
+
+    fn evict(&mut self, key: &str) -> Option<(&'static str, String)> {
+        let entry = self.remove(key)?;
+        self.stats.evictions += 1;
+        self.stats.evicted_bytes += entry.size as u64;
+        Some((entry.db, key.to_string()))
+    }
 }
 
 impl Store for Cache {
     fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
         -> Result<(), Error> {
         let newk = format!("{}:{}", db, key);
-        match self.db.write() {
+        let size = newk.len() + value.len();
+        let evicted: Vec<_> = match self.db.write() {
             Ok(ref mut map) => {
-                map.insert(newk, value);
-                Ok(())
+                map.insert(newk.clone(), value);
+                self.changes.fetch_add(1, Ordering::SeqCst);
+                self.evictor.pushed(db, newk, size).into_iter()
+                    .filter_map(|(db, k)| map.remove(&k).map(|v| (db, k, v)))
+                    .collect()
             },
-            Err(_err) => Err(anyhow!("DB ERROR")),
+            Err(_err) => return Err(anyhow!("DB ERROR")),
+        };
+
+        // signals are emitted without the db lock
+        for (db, k, v) in evicted {
+            self.evictor.emit(db, &k, v);
         }
+        Ok(())
     }
 
     fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
@@ -47,6 +445,7 @@ impl Store for Cache {
         match self.db.read() {
             Ok(map) => {
                 let rdata = map.get(&newk).ok_or(anyhow!("Not found, pull {}", newk))?;
+                self.evictor.used(&newk);
                 formatter(rdata)
             },
             Err(_err) => Err(anyhow!("DB ERROR")),
@@ -56,15 +455,46 @@ impl Store for Cache {
     fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
         -> Result<(), Error>
         where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
         let newk = format!("{}:{}", db, prefix);
         let l = newk.len();
 
         match self.db.read() {
             Ok(map) => {
                 let range = map.range::<String, _>((Included(&newk), Unbounded))
-                    .filter(|(k, _v)| { k.len() >= l && &k[..l] == &newk });
-                for (_, v) in range {
-                    if let Continue(false) = f(v) {
+                    .filter(|(k, _v)| { k.len() >= l && k[..l] == newk });
+                for (k, v) in range {
+                    if let Continue(false) = f(&k[db.len() + 1..], v) {
+                        break;
+                    }
+                };
+
+                Ok(())
+            },
+            Err(_err) => Err(anyhow!("DB ERROR")),
+        }
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let newk = format!("{}:{}", db, prefix);
+        let start = match after >= prefix {
+            true => Excluded(format!("{}:{}", db, after)),
+            false => Included(newk.clone()),
+        };
+
+        match self.db.read() {
+            Ok(map) => {
+                let range = map.range::<String, _>((start, Unbounded))
+                    .take_while(|(k, _v)| k.starts_with(&newk));
+                for (k, v) in range {
+                    if let Continue(false) = f(&k[db.len() + 1..], v) {
                         break;
                     }
                 };
@@ -80,6 +510,8 @@ impl Store for Cache {
         match self.db.write() {
             Ok(ref mut map) => {
                 map.remove(&newk).ok_or(anyhow!("Not found, rm {}", newk))?;
+                self.changes.fetch_add(1, Ordering::SeqCst);
+                self.evictor.removed(&newk);
                 Ok(())
             },
             Err(_err) => Err(anyhow!("DB ERROR")),
@@ -0,0 +1,49 @@
+//! Changes the number of shards of a `ShardedStore` directory.
+//!
+//! ```text
+//! mdl-reshard [--range SPLIT,...] PATH SHARDS
+//! ```
+//!
+//! The keys are hashed by default, with `--range` the shards are split by
+//! the given keys. The objects of all the dbs are moved.
+
+use mdl::Partition;
+use mdl::sharded::reshard;
+
+use std::env;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: mdl-reshard [--range SPLIT,...] PATH SHARDS");
+    process::exit(2);
+}
+
+fn main() {
+    let mut partition = Partition::Hash;
+    let mut rest = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--range" => {
+                let splits = args.next().unwrap_or_else(|| usage());
+                partition = Partition::Range(splits.split(',').map(String::from).collect());
+            }
+            "-h" | "--help" => usage(),
+            _ if !arg.starts_with('-') => rest.push(arg),
+            _ => usage(),
+        }
+    }
+    if rest.len() != 2 {
+        usage();
+    }
+    let shards: usize = rest[1].parse().unwrap_or_else(|_| usage());
+
+    match reshard(&rest[0], shards, partition) {
+        Ok(moved) => eprintln!("{} objects moved to {} shards", moved, shards),
+        Err(err) => {
+            eprintln!("mdl-reshard: {}", err);
+            process::exit(1);
+        }
+    }
+}
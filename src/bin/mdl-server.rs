@@ -0,0 +1,55 @@
+//! Serves a LMDB database to the `RemoteStore` clients.
+//!
+//! ```text
+//! mdl-server [--tcp ADDR | --unix PATH] DB_PATH
+//! ```
+//!
+//! By default it listens in 127.0.0.1:7878.
+
+use mdl::Cache;
+use mdl::Server;
+
+use std::env;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: mdl-server [--tcp ADDR | --unix PATH] DB_PATH");
+    process::exit(2);
+}
+
+fn main() {
+    let mut tcp = "127.0.0.1:7878".to_string();
+    let mut unix = None;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tcp" => tcp = args.next().unwrap_or_else(|| usage()),
+            "--unix" => unix = Some(args.next().unwrap_or_else(|| usage())),
+            "-h" | "--help" => usage(),
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let run = || -> Result<(), anyhow::Error> {
+        let server = Server::new(Cache::new(&path)?)?;
+        let listener = match unix {
+            #[cfg(unix)]
+            Some(ref sock) => server.listen_unix(sock)?,
+            #[cfg(not(unix))]
+            Some(_) => return Err(anyhow::anyhow!("unix sockets are not supported")),
+            None => server.listen_tcp(&tcp)?,
+        };
+        eprintln!("serving {} in {}", path, listener.addr());
+        listener.join();
+        Ok(())
+    };
+
+    if let Err(err) = run() {
+        eprintln!("mdl-server: {}", err);
+        process::exit(1);
+    }
+}
@@ -6,6 +6,7 @@ use lmdb::Cursor;
 use lmdb::Environment;
 use lmdb::Database;
 use lmdb::DatabaseFlags;
+use lmdb::EnvironmentFlags;
 use lmdb::WriteFlags;
 use lmdb::RwCursor;
 use lmdb::RoCursor;
@@ -14,10 +15,11 @@ use std::path::Path;
 use std::fs::create_dir_all;
 use std::collections::HashMap;
 
-use std::cell::RefCell;
+use std::sync::Mutex;
 
 use crate::store::Store;
 use crate::store::Continue;
+use crate::readonly::ReadOnlyError;
 
 /// LMDB cache. This struct implements the Store trait so it can be used
 /// to cache Model structs
@@ -27,14 +29,16 @@ pub struct Cache {
     /// database path in the filesystem
     pub path: String,
     /// List of LMDB databases
-    dbs: RefCell<HashMap<&'static str, Database>>,
+    dbs: Mutex<HashMap<&'static str, Database>>,
+    /// opened with `read_only`
+    read_only: bool,
 }
 
 impl Cache {
     pub fn new(path: &str) -> Result<Cache, Error> {
         let envpath = Path::new(path);
         if !envpath.exists() {
-            let _ = create_dir_all(&envpath);
+            let _ = create_dir_all(envpath);
         }
 
         let env = Environment::new()
@@ -43,19 +47,47 @@ impl Cache {
                     .open(envpath)?;
 
         Ok(Cache {
-            env: env,
+            env,
             path: path.to_string(),
-            dbs: RefCell::new(HashMap::new()),
+            dbs: Mutex::new(HashMap::new()),
+            read_only: false,
         })
     }
 
+    /// Opens an existing database in read-only mode, with the LMDB read-only
+    /// flag, so the files are never modified. `push` and `rm` fail with a
+    /// `ReadOnlyError`, and the dbs that don't exist can't be read
+    pub fn read_only(path: &str) -> Result<Cache, Error> {
+        let env = Environment::new()
+                    .set_flags(EnvironmentFlags::READ_ONLY)
+                    .set_max_dbs(1024)
+                    .open(Path::new(path))
+                    .map_err(|e| anyhow!("error opening {} read-only, {}", path, e))?;
+
+        Ok(Cache {
+            env,
+            path: path.to_string(),
+            dbs: Mutex::new(HashMap::new()),
+            read_only: true,
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn db(&self, name: &'static str) -> Result<Database, Error> {
         // if the db is created, we return the db stored in cache
-        {
-            let dbs = self.dbs.borrow();
-            if dbs.contains_key(name) {
-                return Ok(dbs[name].clone());
-            }
+        let mut dbs = self.dbs.lock().map_err(|_| anyhow!("DB ERROR"))?;
+        if dbs.contains_key(name) {
+            return Ok(dbs[name]);
+        }
+
+        if self.read_only {
+            let db = self.env.open_db(Some(name))
+                .or(Err(anyhow!("error opening the db {}", name)))?;
+            dbs.insert(name, db);
+            return Ok(db);
         }
 
         // if the db doesn't exists, we create that db and store for the future
@@ -63,10 +95,25 @@ impl Cache {
             .create_db(Some(name), DatabaseFlags::default())
             .or(Err(anyhow!("error opening the db {}", name)))?;
 
-        self.dbs.borrow_mut().insert(name, db.clone());
+        dbs.insert(name, db);
         Ok(db)
     }
 
+    /// Names of the dbs in the environment, from the LMDB main db
+    pub fn dbs(&self) -> Result<Vec<String>, Error> {
+        let main = self.env.open_db(None)?;
+        let txn = self.env.begin_ro_txn()?;
+        let mut names = vec![];
+        {
+            let mut cursor = txn.open_ro_cursor(main)?;
+            for (k, _) in cursor.iter_start() {
+                names.push(String::from_utf8(k.to_vec())?);
+            }
+        }
+        txn.commit()?;
+        Ok(names)
+    }
+
     pub fn rw<F, T>(&self, db: &'static str, op: F) -> Result<T, Error>
         where F: Fn(RwCursor) -> Result<T, Error> {
 
@@ -98,9 +145,18 @@ impl Cache {
     }
 }
 
+/// True if the error is the LMDB not found error, returned by `pull` and
+/// `rm` with missing keys and by `iter` if there's no key with the prefix
+pub(crate) fn is_not_found(err: &Error) -> bool {
+    matches!(err.downcast_ref::<lmdb::Error>(), Some(lmdb::Error::NotFound))
+}
+
 impl Store for Cache {
     fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
         -> Result<(), Error> {
+        if self.read_only {
+            return Err(ReadOnlyError::new("push", db, key).into());
+        }
         self.rw(db, move |mut cursor| {
             cursor.put(&key.as_bytes(), &value, WriteFlags::empty())?;
             Ok(())
@@ -120,17 +176,34 @@ impl Store for Cache {
     fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
         -> Result<(), Error>
         where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
         let l = prefix.len();
 
         self.ro(db, move |mut cursor| {
-            let k = Some(prefix.as_ref());
-            cursor.get(k, None, 17)?;
+            let k = prefix.as_bytes();
+            // LMDB doesn't accept empty keys, all the keys have the empty prefix
+            let iter = match k.is_empty() {
+                true => {
+                    cursor.get(None, None, 0)?;
+                    cursor.iter_start()
+                }
+                false => {
+                    cursor.get(Some(k), None, 17)?;
+                    cursor.iter_from(k)
+                }
+            };
 
-            let iter = cursor.iter_from(k.unwrap())
-                .filter(|(k, _v)| { k.len() >= l && &k[0..l] == prefix[0..l].as_bytes() });
+            let iter = iter
+                .filter(|(k, _v)| { k.len() >= l && k[0..l] == prefix.as_bytes()[0..l] });
 
-            for (_, v) in iter {
-                if let Continue(false) = f(v) {
+            for (k, v) in iter {
+                let k = std::str::from_utf8(k).unwrap_or_default();
+                if let Continue(false) = f(k, v) {
                     break;
                 }
             };
@@ -141,7 +214,43 @@ impl Store for Cache {
         Ok(())
     }
 
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let start = match after >= prefix {
+            true => after,
+            false => prefix,
+        };
+        if start.is_empty() {
+            return self.iter_kv(db, prefix, f);
+        }
+
+        self.ro(db, move |mut cursor| {
+            // the cursor is placed in the first key >= start
+            match cursor.get(Some(start.as_bytes()), None, 17) {
+                Ok(_) => {}
+                Err(lmdb::Error::NotFound) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+
+            let iter = cursor.iter_from(start.as_bytes())
+                .take_while(|(k, _v)| k.starts_with(prefix.as_bytes()))
+                .filter(|(k, _v)| *k > after.as_bytes());
+            for (k, v) in iter {
+                let k = std::str::from_utf8(k).unwrap_or_default();
+                if let Continue(false) = f(k, v) {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
     fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        if self.read_only {
+            return Err(ReadOnlyError::new("rm", db, key).into());
+        }
         self.rw(db, move |mut cursor| {
             cursor.get(Some(key.as_ref()), None, 15)?;
             cursor.del(WriteFlags::empty())?;
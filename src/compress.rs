@@ -0,0 +1,242 @@
+//! Value compression, with the `zstd` or `lz4` features.
+//!
+//! The compressed values start with a header, a magic, the format version
+//! and the codec, and the other values are stored raw, so compressed values,
+//! raw values and the values written before enabling the compression can be
+//! mixed in the same db. Small values, and values that don't get smaller, are
+//! stored raw, with the header only if they start with the magic.
+//!
+//! The decompressed size is limited, to `DEFAULT_MAX_SIZE` by default, so a
+//! small corrupted or crafted value can't use all the memory.
+//!
+//! The `Compressed` wrapper compresses all the values of a store. To
+//! compress only some models, override `Model::tob` and `Model::fromb`:
+//!
+//! ```ignore
+//! impl Model for Big {
+//!     fn key(&self) -> String { format!("big:{}", self.id) }
+//!     fn tob(&self) -> Result<Vec<u8>, Error> {
+//!         compress::compress(Codec::Zstd(3), 512, &bincode::serialize(self)?)
+//!     }
+//!     fn fromb(data: &[u8]) -> Result<Self, Error> {
+//!         Ok(bincode::deserialize(&compress::decompress(data)?)?)
+//!     }
+//! }
+//! ```
+
+use anyhow::Error;
+use anyhow::anyhow;
+
+use std::cell::RefCell;
+#[cfg(feature = "zstd")]
+use std::io::Read;
+
+use crate::store::Store;
+use crate::store::Continue;
+
+/// Header: magic, format version and codec
+const MAGIC: &[u8; 4] = b"MDLZ";
+const VERSION: u8 = 1;
+const HEADER: usize = 6;
+
+/// Codec bytes
+const RAW: u8 = 0;
+const ZSTD: u8 = 1;
+const LZ4: u8 = 2;
+
+/// Values smaller than this are stored raw by default
+pub const DEFAULT_THRESHOLD: usize = 512;
+/// Max size of a decompressed value by default
+pub const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/// Compression algorithm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    /// zstd with the compression level, from 1 to 22. 0 is the zstd default
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+/// Compresses the value if it's at least `threshold` bytes long, and adds
+/// the header. The raw values only have the header if they start with the
+/// magic
+pub fn compress(codec: Codec, threshold: usize, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() >= threshold {
+        let (header, compressed) = match codec {
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(level) => (ZSTD, zstd::bulk::compress(data, level)?),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => (LZ4, lz4_flex::compress_prepend_size(data)),
+        };
+        if compressed.len() + HEADER < data.len() {
+            return Ok(with_header(header, &compressed));
+        }
+    }
+
+    match data.starts_with(MAGIC) {
+        true => Ok(with_header(RAW, data)),
+        false => Ok(data.to_vec()),
+    }
+}
+
+/// Decodes a value written by `compress`, with any codec, up to
+/// `DEFAULT_MAX_SIZE` bytes. Values without the header are returned as they
+/// are
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    decompress_max(data, DEFAULT_MAX_SIZE)
+}
+
+/// Decodes a value written by `compress`, failing if the decompressed value
+/// is bigger than `max` bytes
+pub fn decompress_max(data: &[u8], max: usize) -> Result<Vec<u8>, Error> {
+    if data.len() < HEADER || &data[..4] != MAGIC || data[4] != VERSION {
+        return Ok(data.to_vec());
+    }
+
+    let body = &data[HEADER..];
+    match data[5] {
+        RAW => Ok(body.to_vec()),
+        #[cfg(feature = "zstd")]
+        ZSTD => {
+            let mut out = vec![];
+            zstd::stream::read::Decoder::new(body)?
+                .take(max as u64 + 1)
+                .read_to_end(&mut out)?;
+            match out.len() > max {
+                true => Err(too_big(max)),
+                false => Ok(out),
+            }
+        }
+        #[cfg(feature = "lz4")]
+        LZ4 => {
+            // the size goes first, checked before the allocation
+            let size = body.get(..4)
+                .map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]) as usize)
+                .ok_or_else(|| anyhow!("invalid lz4 compressed value"))?;
+            if size > max {
+                return Err(too_big(max));
+            }
+            Ok(lz4_flex::decompress_size_prepended(body)?)
+        }
+        #[cfg(not(feature = "zstd"))]
+        ZSTD => Err(anyhow!("zstd compressed value, the zstd feature is needed")),
+        #[cfg(not(feature = "lz4"))]
+        LZ4 => Err(anyhow!("lz4 compressed value, the lz4 feature is needed")),
+        h => Err(anyhow!("unknown compression codec {}", h)),
+    }
+}
+
+fn with_header(codec: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER + body.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(codec);
+    out.extend_from_slice(body);
+    out
+}
+
+fn too_big(max: usize) -> Error {
+    anyhow!("the decompressed value is bigger than {} bytes", max)
+}
+
+/// Store wrapper that compresses the values, see `compress`. The values
+/// already in the wrapped store are read as they are
+pub struct Compressed<S> {
+    store: S,
+    codec: Codec,
+    threshold: usize,
+    max_size: usize,
+}
+
+impl<S: Store> Compressed<S> {
+    pub fn new(store: S, codec: Codec) -> Compressed<S> {
+        Compressed { store, codec, threshold: DEFAULT_THRESHOLD, max_size: DEFAULT_MAX_SIZE }
+    }
+
+    /// Sets the minimum size of the compressed values
+    pub fn threshold(mut self, threshold: usize) -> Compressed<S> {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the max size of the decompressed values. Bigger values are
+    /// stored raw, so they can be read
+    pub fn max_size(mut self, max_size: usize) -> Compressed<S> {
+        self.max_size = max_size;
+        self
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        decompress_max(data, self.max_size)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: Store> Store for Compressed<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        let threshold = match value.len() > self.max_size {
+            true => usize::MAX,
+            false => self.threshold,
+        };
+        self.store.push(db, key, compress(self.codec, threshold, &value)?)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.store.pull(db, key, |v| formatter(&self.decompress(v)?))
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        let err = RefCell::new(None);
+        self.store.iter(db, prefix, |v| {
+            match self.decompress(v) {
+                Ok(v) => f(&v),
+                Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+            }
+        })?;
+        err.into_inner().map_or(Ok(()), Err)
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let err = RefCell::new(None);
+        self.store.iter_kv(db, prefix, |k, v| {
+            match self.decompress(v) {
+                Ok(v) => f(k, &v),
+                Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+            }
+        })?;
+        err.into_inner().map_or(Ok(()), Err)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let err = RefCell::new(None);
+        self.store.iter_kv_after(db, prefix, after, |k, v| {
+            match self.decompress(v) {
+                Ok(v) => f(k, &v),
+                Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+            }
+        })?;
+        err.into_inner().map_or(Ok(()), Err)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.store.rm(db, key)
+    }
+}
@@ -0,0 +1,270 @@
+//! Directory store, with a file per object, so the data can be edited by
+//! hand.
+//!
+//! The store writes the values as they come, so the models stored here
+//! should be encoded as JSON or TOML, overriding `Model::tob` and
+//! `Model::fromb`:
+//!
+//! ```ignore
+//! impl Model for Config {
+//!     fn key(&self) -> String { format!("config:{}", self.name) }
+//!     fn tob(&self) -> Result<Vec<u8>, Error> { dirstore::to_json(self) }
+//!     fn fromb(data: &[u8]) -> Result<Self, Error> { dirstore::from_json(data) }
+//! }
+//! ```
+
+use anyhow::Error;
+use anyhow::anyhow;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::store::Store;
+use crate::store::Continue;
+use crate::ttl::TTL_DB;
+
+/// Unique temporary file names for concurrent writes of the same key
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Format of the files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+/// Filesystem store, each db is a directory and each key is a file in that
+/// directory, named with the escaped key and the format extension. This
+/// struct implements the Store trait so it can be used to cache Model
+/// structs
+///
+/// The characters that aren't safe in file names, like `:` and `/`, are
+/// escaped as `%XX`. Files are written to a temporary file that replaces the
+/// object file, so a file is never half written. The temporary files of
+/// interrupted writes are removed when the store is opened, so only one
+/// process should write to the directory.
+///
+/// The expiry times of the `ttl` module are stored in the `TTL_DB` directory
+/// encoded with bincode, they aren't meant to be edited.
+pub struct DirStore {
+    /// root directory path in the filesystem
+    pub path: String,
+    format: Format,
+}
+
+impl DirStore {
+    pub fn new(path: &str, format: Format) -> Result<DirStore, Error> {
+        fs::create_dir_all(path)?;
+        remove_tmp_files(Path::new(path))?;
+        Ok(DirStore { path: path.to_string(), format })
+    }
+
+    /// Path of the file of the key
+    pub fn file(&self, db: &str, key: &str) -> PathBuf {
+        self.dir(db).join(format!("{}.{}", escape(key), self.format.ext()))
+    }
+
+    fn dir(&self, db: &str) -> PathBuf {
+        Path::new(&self.path).join(escape(db))
+    }
+
+    /// Checks that the value is in the store format, to fail early with
+    /// models encoded with bincode
+    fn check(&self, db: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+        if db == TTL_DB {
+            return Ok(());
+        }
+        let valid = match self.format {
+            Format::Json => serde_json::from_slice::<serde_json::Value>(value).is_ok(),
+            Format::Toml => toml::from_slice::<toml::Value>(value).is_ok(),
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(anyhow!("{}:{} is not {:?}, the model should encode the value \
+                                  with Model::tob", db, key, self.format)),
+        }
+    }
+}
+
+impl Format {
+    fn ext(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+}
+
+/// Encodes the value as pretty printed JSON
+pub fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    let mut data = serde_json::to_vec_pretty(value)?;
+    data.push(b'\n');
+    Ok(data)
+}
+
+pub fn from_json<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+/// Encodes the value as TOML, the value must be a struct or a map
+pub fn to_toml<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    Ok(toml::to_string_pretty(value)?.into_bytes())
+}
+
+pub fn from_toml<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    Ok(toml::from_slice(data)?)
+}
+
+/// Removes the temporary files of the interrupted writes, in the db
+/// directories
+fn remove_tmp_files(root: &Path) -> Result<(), Error> {
+    for dir in fs::read_dir(root)? {
+        let dir = dir?;
+        if !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(dir.path())? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') && name.ends_with(".tmp") {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Escapes the characters that aren't safe in a file name. The escaping is
+/// done char by char, so the escaped prefix of a key is a prefix of the
+/// escaped key
+fn escape(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for (i, c) in key.chars().enumerate() {
+        let safe = c.is_ascii_alphanumeric() || "-_,@+=".contains(c) ||
+                   (c == '.' && i > 0) || (!c.is_ascii() && !c.is_control());
+        if safe {
+            out.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+    out
+}
+
+fn unescape(name: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = name.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl Store for DirStore {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.check(db, key, &value)?;
+        let dir = self.dir(db);
+        fs::create_dir_all(&dir)?;
+
+        // the escaped names never start with a dot
+        let file = self.file(db, key);
+        let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp = dir.join(format!(".{}.{}-{}.tmp", escape(key), process::id(), n));
+        let mut f = File::create(&tmp)?;
+        f.write_all(&value)?;
+        f.sync_all()?;
+        fs::rename(&tmp, &file)?;
+        // the rename is durable once the directory is synced
+        File::open(&dir)?.sync_all()?;
+        Ok(())
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        match fs::read(self.file(db, key)) {
+            Ok(data) => formatter(&data),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                Err(anyhow!("Not found, pull {}:{}", db, key))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let entries = match fs::read_dir(self.dir(db)) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let ext = format!(".{}", self.format.ext());
+        let escaped = escape(prefix);
+        let mut keys = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            // temporary files and other files edited by hand are ignored
+            if name.starts_with('.') || !name.ends_with(&ext) || !name.starts_with(&escaped) {
+                continue;
+            }
+            if let Some(key) = unescape(&name[..name.len() - ext.len()]) {
+                keys.push((key, entry.path()));
+            }
+        }
+        keys.sort();
+
+        for (key, path) in keys {
+            // the file can be removed while iterating
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if let Continue(false) = f(&key, &data) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.file(db, key)) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                Err(anyhow!("Not found, rm {}:{}", db, key))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
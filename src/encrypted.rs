@@ -0,0 +1,281 @@
+//! Encryption at rest, with the `encryption` feature.
+//!
+//! Values are encrypted with XChaCha20-Poly1305, with a random nonce per
+//! value. Each value starts with a format version byte, the id of the key,
+//! derived from the key, and the nonce. The db and the stored key are
+//! authenticated, so a value can't be moved to another key.
+//!
+//! ```ignore
+//! let store = Encrypted::new(Cache::new("/tmp/db")?, &key).hash_keys(&secret);
+//! token.store(&store)?;
+//! ```
+
+use anyhow::Error;
+use anyhow::anyhow;
+
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use std::cell::RefCell;
+
+use crate::cache::is_not_found;
+use crate::store::Store;
+use crate::store::Continue;
+
+/// Length of the encryption keys
+pub const KEY_LEN: usize = 32;
+
+/// Value header: format version, key id and nonce
+const VERSION: u8 = 1;
+const ID_LEN: usize = 4;
+const NONCE_LEN: usize = 24;
+const HEADER: usize = 1 + ID_LEN + NONCE_LEN;
+
+/// Length in bytes of the hashed key names
+const NAME_LEN: usize = 16;
+
+type KeyId = [u8; ID_LEN];
+
+#[derive(Clone)]
+struct Key {
+    id: KeyId,
+    cipher: XChaCha20Poly1305,
+}
+
+impl Key {
+    fn new(key: &[u8; KEY_LEN]) -> Key {
+        let mut hasher = Sha256::new();
+        hasher.update(b"mdl-key-id");
+        hasher.update(key);
+        let mut id = [0; ID_LEN];
+        id.copy_from_slice(&hasher.finalize()[..ID_LEN]);
+        Key { id, cipher: XChaCha20Poly1305::new(key.into()) }
+    }
+}
+
+/// Store wrapper that encrypts the values with a caller supplied key. This
+/// struct implements the Store trait, so the models don't need to know that
+/// the values are encrypted
+///
+/// The keys are stored in plain text, unless `hash_keys` is used. With
+/// hashed keys the prefix iteration reads and decrypts all the values of
+/// the db, and filters them by the original key, stored in the encrypted
+/// value.
+///
+/// `rotate` re-encrypts the values with a new key. The previous keys are
+/// kept to read the values that weren't re-encrypted, and the values
+/// encrypted with other previous keys can be read adding them with
+/// `old_key`.
+pub struct Encrypted<S> {
+    store: S,
+    key: Key,
+    old_keys: Vec<Key>,
+    /// secret for the key name hashing
+    names: Option<Vec<u8>>,
+}
+
+impl<S: Store> Encrypted<S> {
+    pub fn new(store: S, key: &[u8; KEY_LEN]) -> Encrypted<S> {
+        Encrypted { store, key: Key::new(key), old_keys: vec![], names: None }
+    }
+
+    /// Stores the keys hashed with the secret, so the key names don't leak.
+    /// The secret is not rotated with the encryption key, changing it makes
+    /// the stored values unreachable
+    pub fn hash_keys(mut self, secret: &[u8]) -> Encrypted<S> {
+        self.names = Some(secret.to_vec());
+        self
+    }
+
+    /// Adds a previous key, to read the values encrypted with it
+    pub fn old_key(mut self, key: &[u8; KEY_LEN]) -> Encrypted<S> {
+        self.old_keys.push(Key::new(key));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Encrypts the values of the `dbs` with a new key, in place. The new key
+    /// is used for the next writes. Returns the number of re-encrypted values.
+    /// An interrupted rotation can be resumed calling it again
+    pub fn rotate(&mut self, key: &[u8; KEY_LEN], dbs: &[&'static str]) -> Result<usize, Error> {
+        let key = Key::new(key);
+        if key.id != self.key.id {
+            let old = std::mem::replace(&mut self.key, key);
+            let id = self.key.id;
+            self.old_keys.retain(|k| k.id != id);
+            self.old_keys.insert(0, old);
+        }
+
+        let mut n = 0;
+        for db in dbs {
+            let values = RefCell::new(vec![]);
+            let r = self.store.iter_kv(db, "", |k, v| {
+                if v.get(1..1 + ID_LEN) != Some(&self.key.id[..]) {
+                    values.borrow_mut().push((k.to_string(), v.to_vec()));
+                }
+                Continue(true)
+            });
+            match r {
+                Ok(()) => {}
+                // LMDB fails if the db is empty
+                Err(ref e) if is_not_found(e) => continue,
+                Err(e) => return Err(e),
+            }
+
+            for (name, data) in values.into_inner() {
+                let (key, value) = self.open(db, &name, &data)?;
+                self.store.push(db, &name, self.seal(db, &name, &key, &value)?)?;
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Name of the key in the wrapped store
+    fn name(&self, db: &str, key: &str) -> String {
+        match self.names {
+            Some(ref secret) => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(db.as_bytes());
+                mac.update(&[0]);
+                mac.update(key.as_bytes());
+                mac.finalize().into_bytes()[..NAME_LEN]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
+            }
+            None => key.to_string(),
+        }
+    }
+
+    /// Encrypts the value with the current key. The original key is stored
+    /// with the value, to iterate the hashed keys
+    fn seal(&self, db: &str, name: &str, key: &str, value: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut plain = Vec::with_capacity(4 + key.len() + value.len());
+        plain.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        plain.extend_from_slice(key.as_bytes());
+        plain.extend_from_slice(value);
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = aad(db, name);
+        let encrypted = self.key.cipher.encrypt(&nonce, Payload { msg: &plain, aad: &aad })
+            .map_err(|_| anyhow!("can't encrypt {}:{}", db, key))?;
+
+        let mut out = Vec::with_capacity(HEADER + encrypted.len());
+        out.push(VERSION);
+        out.extend_from_slice(&self.key.id);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&encrypted);
+        Ok(out)
+    }
+
+    /// Decrypts a value, returns the original key and the value
+    fn open(&self, db: &str, name: &str, data: &[u8]) -> Result<(String, Vec<u8>), Error> {
+        if data.len() < HEADER || data[0] != VERSION {
+            return Err(anyhow!("{}:{} is not an encrypted value", db, name));
+        }
+        let id = &data[1..1 + ID_LEN];
+        let key = std::iter::once(&self.key).chain(self.old_keys.iter())
+            .find(|k| k.id == id)
+            .ok_or_else(|| anyhow!("{}:{} is encrypted with an unknown key", db, name))?;
+
+        let nonce = XNonce::from_slice(&data[1 + ID_LEN..HEADER]);
+        let aad = aad(db, name);
+        let plain = key.cipher.decrypt(nonce, Payload { msg: &data[HEADER..], aad: &aad })
+            .map_err(|_| anyhow!("can't decrypt {}:{}, wrong key or corrupted value", db, name))?;
+
+        let invalid = || anyhow!("invalid encrypted value {}:{}", db, name);
+        let len = plain.get(..4).ok_or_else(invalid)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let key = plain.get(4..4 + len).ok_or_else(invalid)?;
+        let key = String::from_utf8(key.to_vec()).map_err(|_| invalid())?;
+        Ok((key, plain[4 + len..].to_vec()))
+    }
+}
+
+/// Authenticated data of a value, the db and the stored key
+fn aad(db: &str, name: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(db.len() + name.len() + 1);
+    aad.extend_from_slice(db.as_bytes());
+    aad.push(0);
+    aad.extend_from_slice(name.as_bytes());
+    aad
+}
+
+impl<S: Store> Store for Encrypted<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        let name = self.name(db, key);
+        self.store.push(db, &name, self.seal(db, &name, key, &value)?)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        let name = self.name(db, key);
+        self.store.pull(db, &name, |v| formatter(&self.open(db, &name, v)?.1))
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let err = RefCell::new(None);
+
+        if self.names.is_none() {
+            self.store.iter_kv(db, prefix, |k, v| {
+                match self.open(db, k, v) {
+                    Ok((_, value)) => f(k, &value),
+                    Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+                }
+            })?;
+            return err.into_inner().map_or(Ok(()), Err);
+        }
+
+        // the hashed keys are not in order and don't keep the prefix
+        let items = RefCell::new(vec![]);
+        self.store.iter_kv(db, "", |k, v| {
+            match self.open(db, k, v) {
+                Ok((key, value)) => {
+                    if key.starts_with(prefix) {
+                        items.borrow_mut().push((key, value));
+                    }
+                    Continue(true)
+                }
+                Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+            }
+        })?;
+        if let Some(e) = err.into_inner() {
+            return Err(e);
+        }
+
+        let mut items = items.into_inner();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        for (k, v) in items {
+            if let Continue(false) = f(&k, &v) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.store.rm(db, &self.name(db, key))
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::Error;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::store::Store;
+use crate::store::Continue;
+use crate::signal::Signaler;
+use crate::signal::Signal;
+use crate::signal::SigType;
+
+/// Store wrapper that records the modifications to undo and redo them.
+///
+/// Each `push` or `rm` is recorded with the previous value, or as a
+/// tombstone if the key didn't exist, so it can be reverted. Operations done
+/// between `begin` and `end`, or inside `step`, are grouped in one step that
+/// is undone and redone at once. Without an open step each operation is a
+/// step.
+///
+/// Only the last `depth` steps are kept. This struct implements clone, all
+/// the clones share the same history.
+///
+/// ```ignore
+/// let store = History::new(Cache::new().unwrap(), 100);
+///
+/// store.step(|s| {
+///     a.store(s)?;
+///     b.delete(s)
+/// }).unwrap();
+///
+/// store.undo_sig(&sig).unwrap(); // a and b as before the step
+/// store.redo_sig(&sig).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct History<S> {
+    store: S,
+    steps: Arc<Mutex<Steps>>,
+}
+
+/// One modification, `None` values mean that the key doesn't exist
+#[derive(Clone, Debug, PartialEq)]
+struct Op {
+    db: &'static str,
+    key: String,
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct Steps {
+    depth: usize,
+    undo: VecDeque<Vec<Op>>,
+    redo: Vec<Vec<Op>>,
+    /// step open with `begin`, with the nesting level
+    current: Option<(usize, Vec<Op>)>,
+}
+
+impl<S: Store> History<S> {
+    /// Creates the history over the `store`, keeping the last `depth` steps
+    pub fn new(store: S, depth: usize) -> History<S> {
+        let steps = Steps { depth, ..Steps::default() };
+        History { store, steps: Arc::new(Mutex::new(steps)) }
+    }
+
+    /// The wrapped store. Modifications done directly in this store are not
+    /// recorded
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    /// Starts a step, all the operations until `end` are grouped. Steps can
+    /// be nested, the step ends with the outer `end`
+    pub fn begin(&self) {
+        let mut steps = self.lock();
+        match steps.current {
+            Some((ref mut level, _)) => *level += 1,
+            None => steps.current = Some((1, vec![])),
+        }
+    }
+
+    /// Ends the step started with `begin`
+    pub fn end(&self) {
+        let mut steps = self.lock();
+        let ops = match steps.current.take() {
+            Some((1, ops)) => ops,
+            Some((level, ops)) => {
+                steps.current = Some((level - 1, ops));
+                return;
+            }
+            None => return,
+        };
+        steps.add(ops);
+    }
+
+    /// Runs `f` in a step. The step ends even if `f` fails, with the
+    /// operations done before the error
+    pub fn step<F, T>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&Self) -> Result<T, Error> {
+        self.begin();
+        let r = f(self);
+        self.end();
+        r
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.lock().undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.lock().redo.is_empty()
+    }
+
+    /// Removes all the recorded steps
+    pub fn clear(&self) {
+        let mut steps = self.lock();
+        steps.undo.clear();
+        steps.redo.clear();
+        steps.current = None;
+    }
+
+    /// Reverts the last step. Returns false if there's nothing to undo
+    pub fn undo(&self) -> Result<bool, Error> {
+        self.replay(true, |_| Ok(()))
+    }
+
+    /// Reverts the last step and emits the signals of the modified keys, like
+    /// `Model::store_sig` and `Model::delete_sig`
+    pub fn undo_sig<G: Signaler>(&self, sig: &G) -> Result<bool, Error> {
+        self.replay(true, |signal| sig.emit_signal(signal))
+    }
+
+    /// Applies again the last undone step. Returns false if there's nothing
+    /// to redo. Recording a new operation clears the redo steps
+    pub fn redo(&self) -> Result<bool, Error> {
+        self.replay(false, |_| Ok(()))
+    }
+
+    /// Applies again the last undone step and emits the signals of the
+    /// modified keys
+    pub fn redo_sig<G: Signaler>(&self, sig: &G) -> Result<bool, Error> {
+        self.replay(false, |signal| sig.emit_signal(signal))
+    }
+
+    fn replay<F>(&self, undo: bool, emit: F) -> Result<bool, Error>
+        where F: Fn(Signal) -> Result<(), Error> {
+        // the step is removed after it's applied, so it's not lost if the
+        // store fails
+        let ops = {
+            let steps = self.lock();
+            let ops = match undo {
+                true => steps.undo.back(),
+                false => steps.redo.last(),
+            };
+            match ops {
+                Some(ops) => ops.clone(),
+                None => return Ok(false),
+            }
+        };
+
+        // the undo goes from the last operation to the first one
+        let ordered: Vec<&Op> = match undo {
+            true => ops.iter().rev().collect(),
+            false => ops.iter().collect(),
+        };
+        for op in ordered {
+            let (from, to) = match undo {
+                true => (&op.new, &op.old),
+                false => (&op.old, &op.new),
+            };
+            let signal = match to {
+                Some(value) => {
+                    self.store.push(op.db, &op.key, value.clone())?;
+                    Signal::new(SigType::Update, &op.key)
+                }
+                None => {
+                    // the key can be already removed
+                    let _ = self.store.rm(op.db, &op.key);
+                    Signal::new(SigType::Delete, &op.key)
+                }
+            };
+            emit(Signal { db: Some(op.db.to_string()), old: from.clone(), new: to.clone(), ..signal })?;
+        }
+
+        let mut steps = self.lock();
+        match undo {
+            true => {
+                if steps.undo.back() == Some(&ops) {
+                    steps.undo.pop_back();
+                }
+                steps.redo.push(ops);
+            }
+            false => {
+                if steps.redo.last() == Some(&ops) {
+                    steps.redo.pop();
+                }
+                steps.undo.push_back(ops);
+                steps.truncate();
+            }
+        }
+        Ok(true)
+    }
+
+    fn record(&self, db: &'static str, key: &str, new: Option<Vec<u8>>) -> Result<(), Error> {
+        let old = self.store.pull(db, key, |data| Ok(data.to_vec())).ok();
+        match new {
+            Some(ref value) => self.store.push(db, key, value.clone())?,
+            None => self.store.rm(db, key)?,
+        }
+
+        let op = Op { db, key: key.to_string(), old, new };
+        let mut steps = self.lock();
+        match steps.current {
+            Some((_, ref mut ops)) => ops.push(op),
+            None => steps.add(vec![op]),
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Steps> {
+        self.steps.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Steps {
+    fn add(&mut self, ops: Vec<Op>) {
+        if ops.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(ops);
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl<S: Store> Store for History<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.record(db, key, Some(value))
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.store.pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.store.iter(db, prefix, f)
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.store.iter_kv(db, prefix, f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.record(db, key, None)
+    }
+}
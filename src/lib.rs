@@ -112,7 +112,7 @@
 //!     // We're using the SignalerAsync so this callback will
 //!     // be called in a different thread, for that reason we're
 //!     // pasing Arc<Mutex<T>> to be able to modify the counters
-//!     let _id = sig.subscribe("b", Box::new(move |sig| {
+//!     let _sub = sig.subscribe("b", Box::new(move |sig| {
 //!         match sig.type_ {
 //!             SigType::Update => *c1.lock().unwrap() += 1,
 //!             SigType::Delete => *c2.lock().unwrap() += 1,
@@ -158,7 +158,7 @@
 //!     let sig = SignalerAsync::new();
 //!     sig.signal_loop();
 //!
-//!     let _id = sig.subscribe("my signal", Box::new(move |sig| {
+//!     let _sub = sig.subscribe("my signal", Box::new(move |sig| {
 //!         println!("my signal is called");
 //!     }));
 //!
@@ -169,23 +169,134 @@
 //!     thread::sleep(ten_millis);
 //! }
 //! ```
+//!
+//! Subscriptions with a string are prefix subscriptions, use `Pattern` to
+//! subscribe with exact names, glob or regex patterns:
+//!
+//! ```ignore
+//! use mdl::Pattern;
+//! use mdl::SignalerAsync;
+//!
+//! fn main() {
+//!     let sig = SignalerAsync::new();
+//!     sig.signal_loop();
+//!
+//!     // "todo:1:done", "todo:a:b:done", ...
+//!     let _sub = sig.subscribe(Pattern::glob("todo:*:done"), Box::new(|sig| {
+//!         println!("{} is done", sig.name);
+//!     }));
+//!
+//!     // only "todo:1"
+//!     let _sub = sig.subscribe(Pattern::exact("todo:1"), Box::new(|_| {}));
+//!
+//!     // "todo:1", "todo:42", ...
+//!     let re = Pattern::regex("^todo:[0-9]+$").unwrap();
+//!     let _sub = sig.subscribe(re, Box::new(|_| {}));
+//! }
+//! ```
+//!
+//! The subscribe methods return a `Subscription` guard, the callback is
+//! unsubscribed when the guard drops. Use `detach` to keep the callback
+//! subscribed, it returns the id to use with `unsubscribe`, and
+//! `subscribe_once` to call the callback only with the first signal:
+//!
+//! ```ignore
+//! let sub = sig.subscribe("todo", Box::new(|_| {})).unwrap();
+//! drop(sub); // unsubscribed
+//!
+//! let id = sig.subscribe("todo", Box::new(|_| {})).unwrap().detach();
+//! sig.unsubscribe(id);
+//!
+//! let _sub = sig.subscribe_once("ready", Box::new(|_| println!("ready")));
+//! ```
+//!
+//! The `Model` trait also provides `subscribe` to receive typed changes of a
+//! model, with the previous and the new value of the object. Only changes in
+//! the model database are received:
+//!
+//! ```ignore
+//! use mdl::Change;
+//! use mdl::Model;
+//! use mdl::SignalerAsync;
+//! use mdl::Subscription;
+//!
+//! fn subscribe(sig: &SignalerAsync) -> Subscription {
+//!     B::subscribe(sig, "b", |change| {
+//!         match change {
+//!             Change::Created(b) => println!("new b {}", b.id),
+//!             Change::Updated { old, new } => println!("{:?} -> {:?}", old, new),
+//!             Change::Deleted(key) => println!("{} deleted", key),
+//!         }
+//!     }).unwrap()
+//! }
+//! ```
 
 pub mod store;
 pub mod cache;
 pub mod bcache;
 pub mod model;
 pub mod signal;
+pub mod pattern;
+pub mod history;
+pub mod ttl;
+pub mod tiered;
+pub mod logstore;
+pub mod remote;
+pub mod sharded;
+pub mod readonly;
+pub mod namespaced;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub mod compress;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+#[cfg(unix)]
+mod notify;
+#[cfg(feature = "async")]
+pub mod async_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "dirstore")]
+pub mod dirstore;
 
 pub use crate::store::Store;
 pub use crate::store::Continue;
+#[cfg(feature = "async")]
+pub use crate::async_store::AsyncStore;
+#[cfg(feature = "async")]
+pub use crate::async_store::AsyncResult;
 pub use cache::Cache;
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite::SqliteCache;
+#[cfg(feature = "dirstore")]
+pub use crate::dirstore::DirStore;
 pub use model::Model;
+pub use model::Change;
 
 pub use bcache::Cache as BCache;
+pub use bcache::{Limits, Policy, EvictionStats, Autosave};
+pub use history::History;
+pub use ttl::Sweeper;
+pub use tiered::{TieredStore, WriteMode};
+pub use logstore::LogStore;
+pub use remote::{RemoteStore, Server};
+pub use sharded::{ShardedStore, Partition};
+pub use readonly::{ReadOnly, ReadOnlyError};
+pub use namespaced::Namespaced;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub use compress::{Compressed, Codec};
+#[cfg(feature = "encryption")]
+pub use encrypted::Encrypted;
 
 pub use crate::signal::Signaler;
 pub use crate::signal::SignalerAsync;
 pub use crate::signal::SignalerSync;
 pub use crate::signal::Signal;
 pub use crate::signal::SigType;
+pub use crate::signal::Waiter;
+pub use crate::signal::Subscription;
+pub use crate::signal::DeliveryError;
+pub use crate::signal::SignalerStatus;
+#[cfg(feature = "async")]
+pub use crate::signal::SignalStream;
+pub use crate::pattern::Pattern;
 
@@ -0,0 +1,424 @@
+use anyhow::Error;
+use anyhow::anyhow;
+
+use crc32fast::hash as crc32;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Bound::{self, Excluded, Included, Unbounded};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::store::Store;
+use crate::store::Continue;
+
+/// File header: magic and format version
+const LOG_MAGIC: &[u8; 4] = b"MDLL";
+const LOG_VERSION: u32 = 1;
+const LOG_HEADER: u64 = 8;
+
+/// Record header: crc32, kind, db, key and value lengths
+const RECORD_HEADER: usize = 15;
+const PUT: u8 = 0;
+const TOMBSTONE: u8 = 1;
+
+/// Append-only log file store. This struct implements the Store trait so it
+/// can be used to cache Model structs in a single file, without LMDB
+///
+/// Each `push` and `rm` appends a record to the file, with a crc32 checksum.
+/// The index with the position of each value is rebuilt when the file is
+/// opened, and an incomplete or corrupted record at the end of the file, from
+/// a crash during a write, is truncated. A corrupted record followed by valid
+/// records can't come from a crash, and opening the file fails. Overwritten
+/// and removed values stay in the file until `compact` rewrites it.
+///
+/// Writes are not synced to disk, call `sync` for that.
+pub struct LogStore {
+    /// log file path in the filesystem
+    pub path: String,
+    log: Mutex<Log>,
+}
+
+struct Log {
+    file: File,
+    /// db -> key -> value position
+    index: BTreeMap<String, BTreeMap<String, Loc>>,
+    /// file size
+    len: u64,
+    /// size of the records that are not live
+    garbage: u64,
+}
+
+/// Position of a value in the file and size of its record
+#[derive(Clone, Copy)]
+struct Loc {
+    offset: u64,
+    len: u32,
+    record: u64,
+}
+
+struct Record {
+    kind: u8,
+    db: String,
+    key: String,
+    value: Vec<u8>,
+}
+
+impl LogStore {
+    /// Opens or creates the log file and rebuilds the index
+    pub fn new(path: &str) -> Result<LogStore, Error> {
+        let log = Log::open(Path::new(path))?;
+        Ok(LogStore { path: path.to_string(), log: Mutex::new(log) })
+    }
+
+    /// Writes the file to disk
+    pub fn sync(&self) -> Result<(), Error> {
+        Ok(self.lock()?.file.sync_data()?)
+    }
+
+    /// Size in bytes of the overwritten and removed records, that `compact`
+    /// would free
+    pub fn garbage(&self) -> u64 {
+        self.lock().map(|log| log.garbage).unwrap_or(0)
+    }
+
+    /// Rewrites the log file with only the live values. The new file is
+    /// written to a temporary file that replaces the log file
+    pub fn compact(&self) -> Result<(), Error> {
+        let mut log = self.lock()?;
+        let path = Path::new(&self.path);
+
+        let mut tmp = PathBuf::from(path);
+        let mut name = path.file_name()
+            .ok_or_else(|| anyhow!("invalid log path {}", self.path))?
+            .to_os_string();
+        name.push(".compact");
+        tmp.set_file_name(name);
+
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(LOG_MAGIC)?;
+        out.write_all(&LOG_VERSION.to_le_bytes())?;
+        let mut len = LOG_HEADER;
+        let mut index = log.index.clone();
+        for (db, keys) in index.iter_mut() {
+            for (key, loc) in keys.iter_mut() {
+                let value = log.read(*loc)?;
+                out.write_all(&encode(PUT, db, key, &value))?;
+                *loc = Loc::new(len, db, key, &value);
+                len += loc.record;
+            }
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        log.file = OpenOptions::new().read(true).append(true).open(path)?;
+        log.index = index;
+        log.len = len;
+        log.garbage = 0;
+
+        // the rename is durable once the directory is synced
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Calls `f` with the keys with the prefix from `start`. The values are
+    /// read from the log between the index lookups, so only the visited keys
+    /// are read
+    fn iter_from<F>(&self, db: &str, prefix: &str, mut start: Bound<String>, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let mut log = self.lock()?;
+        loop {
+            let next = log.index.get(db).and_then(|keys| {
+                keys.range::<str, _>((start.as_ref().map(|s| s.as_str()), Unbounded))
+                    .next()
+                    .filter(|(k, _)| k.starts_with(prefix))
+                    .map(|(k, l)| (k.clone(), *l))
+            });
+            let (key, loc) = match next {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+
+            let value = log.read(loc)?;
+            if let Continue(false) = f(&key, &value) {
+                return Ok(());
+            }
+            start = Excluded(key);
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Log>, Error> {
+        self.log.lock().map_err(|_| anyhow!("DB ERROR"))
+    }
+}
+
+impl Log {
+    fn open(path: &Path) -> Result<Log, Error> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut size = file.metadata()?.len();
+        // a new file, or a crash while the header was written, there are no
+        // records yet. Other short files are not log files
+        if size < LOG_HEADER {
+            let mut header = LOG_MAGIC.to_vec();
+            header.extend_from_slice(&LOG_VERSION.to_le_bytes());
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            if !header.starts_with(&data) {
+                return Err(anyhow!("{} is not a log file", path.display()));
+            }
+            file.set_len(0)?;
+            file.write_all(LOG_MAGIC)?;
+            file.write_all(&LOG_VERSION.to_le_bytes())?;
+            size = LOG_HEADER;
+        } else {
+            let mut header = [0; LOG_HEADER as usize];
+            file.read_exact(&mut header)
+                .map_err(|_| anyhow!("{} is not a log file", path.display()))?;
+            if &header[0..4] != LOG_MAGIC {
+                return Err(anyhow!("{} is not a log file", path.display()));
+            }
+            let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if version != LOG_VERSION {
+                return Err(anyhow!("unsupported log version {}, {}", version, path.display()));
+            }
+        }
+
+        let mut log = Log { file, index: BTreeMap::new(), len: LOG_HEADER, garbage: 0 };
+        log.rebuild(size)
+            .map_err(|e| anyhow!("error opening {}, {}", path.display(), e))?;
+        Ok(log)
+    }
+
+    /// Reads all the records to build the index, truncating the torn tail
+    /// after the last valid record
+    fn rebuild(&mut self, size: u64) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(LOG_HEADER))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut offset = LOG_HEADER;
+        let mut records = vec![];
+        while let Some(record) = read_record(&mut reader, size - offset)? {
+            let loc = Loc::new(offset, &record.db, &record.key, &record.value);
+            offset += loc.record;
+            records.push((record.kind, record.db, record.key, loc));
+        }
+        for (kind, db, key, loc) in records {
+            self.apply(kind, db, key, loc);
+        }
+
+        if offset < size {
+            // a crash only leaves a bad tail, a valid record after the
+            // invalid one is a corruption in the middle of the file
+            if self.valid_after(offset, size)? {
+                return Err(anyhow!("corrupted record at {}", offset));
+            }
+            self.file.set_len(offset)?;
+        }
+        self.len = offset;
+        Ok(())
+    }
+
+    /// True if there's a valid record after the invalid record in `offset`.
+    /// The tail is scanned once, skipping each invalid record with the
+    /// lengths of its header. A torn record is the last one, it has no valid
+    /// record after it
+    fn valid_after(&mut self, mut offset: u64, size: u64) -> Result<bool, Error> {
+        loop {
+            let mut header = [0; RECORD_HEADER];
+            self.file.seek(SeekFrom::Start(offset))?;
+            if size - offset < RECORD_HEADER as u64 || !read_all(&mut self.file, &mut header)? {
+                return Ok(false);
+            }
+            offset += RECORD_HEADER as u64 + body_len(&header);
+            if offset >= size {
+                return Ok(false);
+            }
+
+            self.file.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(&self.file);
+            if read_record(&mut reader, size - offset)?.is_some() {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Updates the index with a record
+    fn apply(&mut self, kind: u8, db: String, key: String, loc: Loc) {
+        let keys = self.index.entry(db).or_default();
+        let old = match kind {
+            PUT => keys.insert(key, loc),
+            _ => {
+                self.garbage += loc.record;
+                keys.remove(&key)
+            }
+        };
+        if let Some(old) = old {
+            self.garbage += old.record;
+        }
+    }
+
+    fn append(&mut self, kind: u8, db: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+        // the record header lengths are u16 and u32
+        if db.len() > u16::MAX as usize {
+            return Err(anyhow!("db name too long, {} bytes", db.len()));
+        }
+        if key.len() > u32::MAX as usize || value.len() > u32::MAX as usize {
+            return Err(anyhow!("key or value too long in {}, {} and {} bytes",
+                               db, key.len(), value.len()));
+        }
+
+        let record = encode(kind, db, key, value);
+        let loc = Loc::new(self.len, db, key, value);
+        if let Err(err) = self.file.write_all(&record) {
+            // removes the partial record, if any
+            let _ = self.file.set_len(self.len);
+            return Err(err.into());
+        }
+        self.len += loc.record;
+        self.apply(kind, db.to_string(), key.to_string(), loc);
+        Ok(())
+    }
+
+    fn read(&mut self, loc: Loc) -> Result<Vec<u8>, Error> {
+        let mut value = vec![0; loc.len as usize];
+        self.file.seek(SeekFrom::Start(loc.offset))?;
+        self.file.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    fn get(&self, db: &str, key: &str) -> Option<Loc> {
+        self.index.get(db).and_then(|keys| keys.get(key)).copied()
+    }
+}
+
+impl Loc {
+    fn new(offset: u64, db: &str, key: &str, value: &[u8]) -> Loc {
+        let header = (RECORD_HEADER + db.len() + key.len()) as u64;
+        Loc {
+            offset: offset + header,
+            len: value.len() as u32,
+            record: header + value.len() as u64,
+        }
+    }
+}
+
+fn encode(kind: u8, db: &str, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(RECORD_HEADER + db.len() + key.len() + value.len());
+    body.push(kind);
+    body.extend_from_slice(&(db.len() as u16).to_le_bytes());
+    body.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    body.extend_from_slice(db.as_bytes());
+    body.extend_from_slice(key.as_bytes());
+    body.extend_from_slice(value);
+
+    let mut record = crc32(&body).to_le_bytes().to_vec();
+    record.extend_from_slice(&body);
+    record
+}
+
+/// Reads the next record. Returns `None` at the end of the file or if the
+/// record is incomplete or corrupted
+fn read_record<R: Read>(reader: &mut R, available: u64) -> Result<Option<Record>, Error> {
+    let mut header = [0; RECORD_HEADER];
+    if (available as usize) < RECORD_HEADER || !read_all(reader, &mut header)? {
+        return Ok(None);
+    }
+
+    let crc = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let kind = header[4];
+    let db_len = u16::from_le_bytes([header[5], header[6]]) as u64;
+    let key_len = u32::from_le_bytes([header[7], header[8], header[9], header[10]]) as u64;
+
+    // the lengths of a torn record can be anything
+    let len = body_len(&header);
+    if len > available - RECORD_HEADER as u64 || (kind != PUT && kind != TOMBSTONE) {
+        return Ok(None);
+    }
+    let mut body = vec![0; len as usize];
+    if !read_all(reader, &mut body)? {
+        return Ok(None);
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header[4..]);
+    hasher.update(&body);
+    if hasher.finalize() != crc {
+        return Ok(None);
+    }
+
+    let value = body.split_off((db_len + key_len) as usize);
+    let key = body.split_off(db_len as usize);
+    match (String::from_utf8(body), String::from_utf8(key)) {
+        (Ok(db), Ok(key)) => Ok(Some(Record { kind, db, key, value })),
+        _ => Ok(None),
+    }
+}
+
+/// Size of the db, key and value of the record header
+fn body_len(header: &[u8; RECORD_HEADER]) -> u64 {
+    let db_len = u16::from_le_bytes([header[5], header[6]]) as u64;
+    let key_len = u32::from_le_bytes([header[7], header[8], header[9], header[10]]) as u64;
+    let value_len = u32::from_le_bytes([header[11], header[12], header[13], header[14]]) as u64;
+    db_len + key_len + value_len
+}
+
+/// Fills the buffer, returns false at the end of the file
+fn read_all<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, Error> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl Store for LogStore {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.lock()?.append(PUT, db, key, &value)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        let mut log = self.lock()?;
+        let loc = log.get(db, key).ok_or_else(|| anyhow!("Not found, pull {}:{}", db, key))?;
+        let value = log.read(loc)?;
+        formatter(&value)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.iter_from(db, prefix, Included(prefix.to_string()), f)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let start = match after >= prefix {
+            true => Excluded(after.to_string()),
+            false => Included(prefix.to_string()),
+        };
+        self.iter_from(db, prefix, start, f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        let mut log = self.lock()?;
+        if log.get(db, key).is_none() {
+            return Err(anyhow!("Not found, rm {}:{}", db, key));
+        }
+        log.append(TOMBSTONE, db, key, &[])
+    }
+}
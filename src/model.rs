@@ -1,12 +1,58 @@
 use serde;
 use anyhow::Error;
+use anyhow::anyhow;
 use bincode::{serialize, deserialize};
 
+use std::time::Duration;
+
 use crate::store::Store;
 use crate::store::Continue;
 
 use crate::signal::Signaler;
+use crate::signal::Signal;
+use crate::signal::Subscription;
 use crate::signal::SigType;
+use crate::pattern::Pattern;
+use crate::ttl;
+
+#[cfg(feature = "async")]
+use crate::async_store::{AsyncStore, AsyncResult};
+
+
+/// Model modification received by the `Model::subscribe` callbacks
+#[derive(Debug)]
+pub enum Change<M> {
+    /// The model was stored and there was no previous value for the key
+    Created(M),
+    /// The model was stored replacing the `old` value
+    Updated { old: M, new: M },
+    /// The model with this key was deleted
+    Deleted(String),
+}
+
+impl<M: Model> Change<M> {
+    /// Builds the change from a signal emitted by `store_sig` or
+    /// `delete_sig`. Returns `None` if the signal comes from other database
+    /// or if the values can't be deserialized as `M`
+    pub fn from_signal(sig: &Signal) -> Option<Change<M>> {
+        if sig.db.as_deref() != Some(M::db()) {
+            return None;
+        }
+
+        match (&sig.type_, &sig.old, &sig.new) {
+            (SigType::Delete, _, _) => Some(Change::Deleted(sig.name.clone())),
+            (SigType::Update, None, Some(new)) => {
+                M::fromb(new).ok().map(Change::Created)
+            }
+            (SigType::Update, Some(old), Some(new)) => {
+                let old = M::fromb(old).ok()?;
+                let new = M::fromb(new).ok()?;
+                Some(Change::Updated { old, new })
+            }
+            _ => None,
+        }
+    }
+}
 
 
 /// Trait to implement Cacheable data Model
@@ -19,6 +65,16 @@ pub trait Model: serde::Serialize +
     /// database name, where to store instances of this struct
     fn db() -> &'static str { "default" }
 
+    /// time to live of the stored objects, `None` means that the objects
+    /// don't expire. Expired objects are treated as missing, see `ttl`
+    fn ttl() -> Option<Duration> { None }
+
+    /// true if the objects can expire, with `Model::ttl` or with
+    /// `store_with_ttl`. The expiry times are only read and written for the
+    /// models that can expire, models without `ttl` that use
+    /// `store_with_ttl` should return true
+    fn expires() -> bool { Self::ttl().is_some() }
+
     /// Data Struct serialization
     fn tob(&self) -> Result<Vec<u8>, Error> {
         let encoded: Vec<u8> = serialize(self)?;
@@ -31,18 +87,49 @@ pub trait Model: serde::Serialize +
         Ok(decoded)
     }
 
-    /// Persist the struct in the database
+    /// Persist the struct in the database, with the `Model::ttl` time to live
     fn store<S: Store>(&self, store: &S)
         -> Result<(), Error> {
-        store.push(Self::db(), &self.key(), self.tob()?)
+        let key = self.key();
+        store.push(Self::db(), &key, self.tob()?)?;
+        match Self::ttl() {
+            Some(t) => ttl::expire(store, Self::db(), &key, t)?,
+            // removes the expiry time of a previous store_with_ttl
+            None if Self::expires() => ttl::persist(store, Self::db(), &key),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Persist the struct in the database, it will be treated as missing
+    /// after the `ttl` time
+    fn store_with_ttl<S: Store>(&self, store: &S, ttl: Duration)
+        -> Result<(), Error> {
+        let key = self.key();
+        if !Self::expires() {
+            return Err(anyhow!("{} can't expire, Model::expires should return true", key));
+        }
+        store.push(Self::db(), &key, self.tob()?)?;
+        ttl::expire(store, Self::db(), &key, ttl)
     }
 
     /// Persist the struct in the database and emit the signal to the signaler
+    ///
+    /// The signal contains the previous and the new serialized value, so
+    /// callbacks subscribed with `Model::subscribe` can know what changed
     fn store_sig<S: Store, G: Signaler>(&self, store: &S, sig: &G)
         -> Result<(), Error> {
+        let key = self.key();
+        let old = store.pull(Self::db(), &key, |data| Ok(data.to_vec())).ok();
+        let new = self.tob()?;
+
         self.store(store)
             .and_then(|out| {
-                sig.emit(SigType::Update, &self.key())?;
+                let mut signal = Signal::new(SigType::Update, &key);
+                signal.db = Some(Self::db().to_string());
+                signal.old = old;
+                signal.new = Some(new);
+                sig.emit_signal(signal)?;
                 Ok(out)
             })
     }
@@ -50,36 +137,166 @@ pub trait Model: serde::Serialize +
     /// Deletes the object from the database
     fn delete<S: Store>(&self, store: &S)
         -> Result<(), Error> {
-        store.rm(Self::db(), &self.key())
+        let key = self.key();
+        store.rm(Self::db(), &key)?;
+        if Self::expires() {
+            ttl::persist(store, Self::db(), &key);
+        }
+        Ok(())
     }
 
     /// Deletes the object from the database and emit the signal to the signaler
     fn delete_sig<S: Store, G: Signaler>(&self, store: &S, sig: &G)
         -> Result<(), Error> {
+        let key = self.key();
+        let old = store.pull(Self::db(), &key, |data| Ok(data.to_vec())).ok();
+
         self.delete(store)
             .and_then(|out| {
-                sig.emit(SigType::Delete, &self.key())?;
+                let mut signal = Signal::new(SigType::Delete, &key);
+                signal.db = Some(Self::db().to_string());
+                signal.old = old;
+                sig.emit_signal(signal)?;
                 Ok(out)
             })
     }
 
+    /// Subscribe to the modifications of objects of this model which key
+    /// matches the pattern. Only the modifications done with `store_sig` and
+    /// `delete_sig` in this model database call the callback, so models with
+    /// the same key prefix in other databases are ignored.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    fn subscribe<G, P, F>(sig: &G, pattern: P, f: F) -> Result<Subscription, Error>
+        where G: Signaler,
+              P: Into<Pattern>,
+              F: Fn(Change<Self>) + Send + 'static,
+              Self: 'static {
+        sig.connect(pattern.into(), Box::new(move |signal| {
+            if let Some(change) = Change::from_signal(&signal) {
+                f(change);
+            }
+        }))
+    }
+
     /// Loads the struct from the database
     fn get<S: Store>(store: &S, key: &str) -> Result<Self, Error> {
+        if Self::expires() && ttl::is_expired(store, Self::db(), key) {
+            return Err(anyhow!("Not found, expired {}", key));
+        }
         store.pull(Self::db(), key, Self::fromb)
     }
 
     /// Get all objects with this prefix
     fn all<S: Store>(store: &S, prefix: &str)
         -> Result<Vec<Self>, Error> {
-        store.all(Self::db(), prefix, Self::fromb)
+        let mut all = store.all(Self::db(), prefix, Self::fromb)?;
+        if !Self::expires() {
+            return Ok(all);
+        }
+        let expired = ttl::expired(store, Self::db(), prefix);
+        if !expired.is_empty() {
+            all.retain(|obj| !expired.contains(&obj.key()));
+        }
+        Ok(all)
+    }
+
+    /// Persist the struct in the database from async code, with the
+    /// `Model::ttl` time to live
+    #[cfg(feature = "async")]
+    fn store_async<S: AsyncStore>(&self, store: &S)
+        -> AsyncResult<()> {
+        let key = self.key();
+        let push = self.tob()
+            .map(|value| store.push_async(Self::db(), &key, value));
+        let expiry = match Self::ttl() {
+            Some(t) => Some(ttl::expire_async(store, Self::db(), &key, t)),
+            None if Self::expires() => Some(ttl::persist_async(store, Self::db(), &key)),
+            None => None,
+        };
+        Box::pin(async move {
+            push?.await?;
+            match expiry {
+                Some(expiry) => expiry.await,
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Deletes the object from the database from async code
+    #[cfg(feature = "async")]
+    fn delete_async<S: AsyncStore>(&self, store: &S)
+        -> AsyncResult<()> {
+        let key = self.key();
+        let rm = store.rm_async(Self::db(), &key);
+        let persist = match Self::expires() {
+            true => Some(ttl::persist_async(store, Self::db(), &key)),
+            false => None,
+        };
+        Box::pin(async move {
+            rm.await?;
+            match persist {
+                Some(persist) => persist.await,
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Loads the struct from the database from async code
+    #[cfg(feature = "async")]
+    fn get_async<S: AsyncStore>(store: &S, key: &str)
+        -> AsyncResult<Self>
+        where Self: Send + 'static {
+        let expired = match Self::expires() {
+            true => Some(ttl::is_expired_async(store, Self::db(), key)),
+            false => None,
+        };
+        let pull = store.pull_async(Self::db(), key, Self::fromb);
+        let key = key.to_string();
+        Box::pin(async move {
+            if let Some(expired) = expired {
+                if expired.await? {
+                    return Err(anyhow!("Not found, expired {}", key));
+                }
+            }
+            pull.await
+        })
+    }
+
+    /// Get all objects with this prefix from async code
+    #[cfg(feature = "async")]
+    fn all_async<S: AsyncStore>(store: &S, prefix: &str)
+        -> AsyncResult<Vec<Self>>
+        where Self: Send + 'static {
+        let expired = match Self::expires() {
+            true => Some(ttl::expired_async(store, Self::db(), prefix)),
+            false => None,
+        };
+        let all = store.all_async(Self::db(), prefix, Self::fromb);
+        Box::pin(async move {
+            let mut all = all.await?;
+            if let Some(expired) = expired {
+                let expired = expired.await?;
+                all.retain(|obj| !expired.contains(&obj.key()));
+            }
+            Ok(all)
+        })
     }
 
     /// Iterate over all objects with this prefix
     fn iter<S, F>(store: &S, prefix: &str, f: F) -> Result<(), Error>
         where S: Store,
               F: Fn(Self) -> Continue {
+        // collected before the iteration, stores can't be read while
+        // iterating
+        let expired = match Self::expires() {
+            true => ttl::expired(store, Self::db(), prefix),
+            false => Default::default(),
+        };
         store.iter(Self::db(), prefix, move |data| {
             match Self::fromb(data) {
+                Ok(ref obj) if expired.contains(&obj.key()) => Continue(true),
                 Ok(obj) => f(obj),
                 _ => Continue(true)
             }
@@ -0,0 +1,174 @@
+use anyhow::Error;
+use anyhow::anyhow;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::cache::is_not_found;
+use crate::store::Store;
+use crate::store::Continue;
+use crate::ttl::TTL_DB;
+
+/// Separator between the namespace and the key
+const SEPARATOR: char = '/';
+/// Database where the dbs used by each namespace are stored, with the key
+/// `namespace/db`
+pub const NAMESPACES_DB: &str = "mdl-namespaces";
+
+/// Store view that prefixes all the keys with a namespace, so several
+/// tenants can share a store and the models don't need to know the tenant.
+/// The keys are stored as `namespace/key`, and the namespace is removed in
+/// the keys given to `iter_kv`.
+///
+/// The dbs used by the namespace are stored in the `NAMESPACES_DB`, so
+/// `clear` can remove all the namespace objects, also after a restart.
+///
+/// To share a `Cache` between tenants, wrap it in an `Arc`:
+///
+/// ```ignore
+/// let cache = Arc::new(Cache::new("/tmp/db")?);
+/// let tenant = Namespaced::new(cache.clone(), "tenant1")?;
+/// a.store(&tenant)?;
+/// ```
+pub struct Namespaced<S> {
+    store: S,
+    namespace: String,
+    prefix: String,
+    /// dbs stored in the `NAMESPACES_DB`
+    dbs: Mutex<HashSet<&'static str>>,
+}
+
+impl<S: Store> Namespaced<S> {
+    /// The namespace can't be empty nor contain `/`
+    pub fn new(store: S, namespace: &str) -> Result<Namespaced<S>, Error> {
+        if namespace.is_empty() || namespace.contains(SEPARATOR) {
+            return Err(anyhow!("invalid namespace {:?}, it can't be empty nor contain {:?}",
+                               namespace, SEPARATOR));
+        }
+        Ok(Namespaced {
+            store,
+            namespace: namespace.to_string(),
+            prefix: format!("{}{}", namespace, SEPARATOR),
+            dbs: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Removes all the objects of the namespace, in all the dbs used by the
+    /// namespace, and their expiry times. Returns the number of removed
+    /// objects. The store must support `iter_kv`
+    pub fn clear(&self) -> Result<usize, Error> {
+        // the writes to new dbs wait, so all the dbs are cleared
+        let mut known = self.lock();
+        let n = self.prefix.len();
+        let names = self.keys(NAMESPACES_DB)?;
+
+        let mut removed = 0;
+        for name in names.iter() {
+            let name = &name[n..];
+            let db = match known.get(name) {
+                Some(db) => *db,
+                // the db names live until the end of the program, they are few
+                None => Box::leak(name.to_string().into_boxed_str()),
+            };
+            for key in self.keys(db)? {
+                self.store.rm(db, &key)?;
+                removed += 1;
+            }
+        }
+        // the expiry times of the objects, also from before the dbs were
+        // stored
+        for key in self.keys(TTL_DB)? {
+            self.store.rm(TTL_DB, &key)?;
+        }
+        for key in names {
+            self.store.rm(NAMESPACES_DB, &key)?;
+        }
+        known.clear();
+        Ok(removed)
+    }
+
+    /// The keys of the namespace in the db, with the namespace
+    fn keys(&self, db: &'static str) -> Result<Vec<String>, Error> {
+        let keys = RefCell::new(vec![]);
+        let r = self.store.iter_kv(db, &self.prefix, |k, _| {
+            keys.borrow_mut().push(k.to_string());
+            Continue(true)
+        });
+        match r {
+            Ok(()) => Ok(keys.into_inner()),
+            // LMDB fails if there's no key with the prefix
+            Err(ref e) if is_not_found(e) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores that the namespace uses the db, before the first write
+    fn track(&self, db: &'static str) -> Result<(), Error> {
+        let mut known = self.lock();
+        if db == TTL_DB || known.contains(db) {
+            return Ok(());
+        }
+        self.store.push(NAMESPACES_DB, &self.key(db), vec![])?;
+        known.insert(db);
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashSet<&'static str>> {
+        self.dbs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl<S: Store> Store for Namespaced<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.track(db)?;
+        self.store.push(db, &self.key(key), value)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.store.pull(db, &self.key(key), formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.store.iter(db, &self.key(prefix), f)
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let n = self.prefix.len();
+        self.store.iter_kv(db, &self.key(prefix), |k, v| f(&k[n..], v))
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let n = self.prefix.len();
+        self.store.iter_kv_after(db, &self.key(prefix), &self.key(after), |k, v| f(&k[n..], v))
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.store.rm(db, &self.key(key))
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Error;
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+/// Socket pair used to wake up external main loops. The read end is
+/// readable while there are pending signals
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    rx: UnixStream,
+    tx: UnixStream,
+}
+
+impl Notifier {
+    pub fn new() -> Result<Notifier, Error> {
+        let (rx, tx) = UnixStream::pair()?;
+        rx.set_nonblocking(true)?;
+        tx.set_nonblocking(true)?;
+        Ok(Notifier { rx, tx })
+    }
+
+    /// Makes the read end readable
+    pub fn notify(&self) {
+        // if the buffer is full, the fd is already readable
+        let _ = (&self.tx).write(&[1]);
+    }
+
+    /// Consumes the notifications, the fd is not readable after this call
+    pub fn clear(&self) {
+        let mut buf = [0; 64];
+        loop {
+            match (&self.rx).read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.rx.as_raw_fd()
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::Error;
+use regex::Regex;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Subscription pattern used to decide which signals trigger a callback
+///
+///   pattern                        | matches
+///   -------------------------------+----------------------------------------
+///   Prefix("todo")                 | every signal that starts with "todo"
+///   Exact("todo:1")                | only the "todo:1" signal
+///   Glob("todo:*:done")            | "todo:1:done", "todo:a:b:done", ...
+///   Regex("^todo:[0-9]+$")         | "todo:1", "todo:42", ...
+///
+/// A `&str` or `String` can be used as a `Pattern` and it will be a prefix
+/// pattern, to keep the behaviour of the plain `subscribe` calls.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Prefix(String),
+    Exact(String),
+    /// `*` matches any sequence of chars, including the empty one, and `?`
+    /// matches exactly one char
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn prefix(p: &str) -> Pattern {
+        Pattern::Prefix(p.to_string())
+    }
+
+    pub fn exact(p: &str) -> Pattern {
+        Pattern::Exact(p.to_string())
+    }
+
+    pub fn glob(p: &str) -> Pattern {
+        Pattern::Glob(p.to_string())
+    }
+
+    /// Returns an error if `p` is not a valid regular expression
+    pub fn regex(p: &str) -> Result<Pattern, Error> {
+        Ok(Pattern::Regex(Regex::new(p)?))
+    }
+
+    /// Returns true if the signal `name` matches this pattern
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Pattern::Prefix(p) => name.starts_with(&p[..]),
+            Pattern::Exact(p) => name == p,
+            Pattern::Glob(p) => glob_match(p.as_bytes(), name.as_bytes()),
+            Pattern::Regex(r) => r.is_match(name),
+        }
+    }
+
+    /// Literal text that every matching signal should start with. This is
+    /// used as the position of the pattern in the dispatch trie
+    pub(crate) fn anchor(&self) -> &str {
+        match self {
+            Pattern::Prefix(p) | Pattern::Exact(p) => p,
+            Pattern::Glob(p) => {
+                let end = p.find(['*', '?']).unwrap_or(p.len());
+                &p[..end]
+            }
+            Pattern::Regex(r) => regex_anchor(r.as_str()),
+        }
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(p: &str) -> Pattern {
+        Pattern::prefix(p)
+    }
+}
+
+impl From<&String> for Pattern {
+    fn from(p: &String) -> Pattern {
+        Pattern::prefix(p)
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(p: String) -> Pattern {
+        Pattern::Prefix(p)
+    }
+}
+
+/// Trie of patterns, indexed by the pattern literal anchor. Looking up a
+/// signal only visits the nodes in the path of the signal name, so the cost
+/// depends on the signal length and not in the number of subscriptions.
+///
+/// Prefix and exact patterns are resolved just by the node position, glob and
+/// regex patterns are stored in the node of the literal part and checked
+/// against the full name when that node is visited.
+///
+/// Each value has an id, given on insertion, and the entries of each node are
+/// indexed by id, so removing a value with its anchor doesn't depend on the
+/// number of values.
+#[derive(Debug)]
+pub(crate) struct Dispatcher<T> {
+    root: Node<T>,
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    children: BTreeMap<u8, Node<T>>,
+    /// id -> entry, the ids are increasing so they give the insertion order
+    entries: HashMap<u32, (Pattern, T)>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Node<T> {
+        Node { children: BTreeMap::new(), entries: HashMap::new() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.children.is_empty()
+    }
+
+    fn remove(&mut self, path: &[u8], id: u32) -> Option<(Pattern, T)> {
+        if path.is_empty() {
+            return self.entries.remove(&id);
+        }
+
+        let child = self.children.get_mut(&path[0])?;
+        let found = child.remove(&path[1..], id);
+        if child.is_empty() {
+            self.children.remove(&path[0]);
+        }
+        found
+    }
+}
+
+impl<T> Dispatcher<T> {
+    pub fn new() -> Dispatcher<T> {
+        Dispatcher { root: Node::new() }
+    }
+
+    /// Inserts the value with the `id`, the ids must be increasing
+    pub fn insert(&mut self, id: u32, pattern: Pattern, value: T) {
+        let mut node = &mut self.root;
+        for b in pattern.anchor().bytes() {
+            node = node.children.entry(b).or_insert_with(Node::new);
+        }
+        node.entries.insert(id, (pattern, value));
+    }
+
+    /// Removes the value with the `id` in the node of the pattern `anchor`,
+    /// so it's not needed to visit all the trie nor the other values
+    pub fn remove(&mut self, anchor: &str, id: u32) -> Option<(Pattern, T)> {
+        self.root.remove(anchor.as_bytes(), id)
+    }
+
+    /// Removes all the prefix patterns that are exactly `prefix` and returns
+    /// the removed values
+    pub fn remove_prefix(&mut self, prefix: &str) -> Vec<T> {
+        let mut node = &mut self.root;
+        for b in prefix.bytes() {
+            node = match node.children.get_mut(&b) {
+                Some(n) => n,
+                None => return vec![],
+            };
+        }
+
+        let mut ids: Vec<u32> = node.entries.iter()
+            .filter(|(_, (p, _))| matches!(p, Pattern::Prefix(p) if p == prefix))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids.into_iter()
+            .filter_map(|id| node.entries.remove(&id))
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// All values which pattern matches the signal `name`. Values are
+    /// returned from the shortest anchor to the longest one and in insertion
+    /// order for the same anchor
+    pub fn matches(&self, name: &str) -> Vec<&T> {
+        let mut out = vec![];
+        let bytes = name.as_bytes();
+        let mut node = &self.root;
+        let mut depth = 0;
+
+        loop {
+            let mut found: Vec<(u32, &T)> = node.entries.iter()
+                .filter(|(_, (p, _))| match p {
+                    Pattern::Prefix(_) => true,
+                    Pattern::Exact(_) => depth == bytes.len(),
+                    _ => p.matches(name),
+                })
+                .map(|(id, (_, v))| (*id, v))
+                .collect();
+            found.sort_by_key(|(id, _)| *id);
+            out.extend(found.into_iter().map(|(_, v)| v));
+
+            if depth == bytes.len() {
+                break;
+            }
+            node = match node.children.get(&bytes[depth]) {
+                Some(n) => n,
+                None => break,
+            };
+            depth += 1;
+        }
+
+        out
+    }
+
+    pub fn clear(&mut self) {
+        self.root = Node::new();
+    }
+}
+
+/// Simple glob matching, `*` matches any sequence and `?` one char
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and the name position matched
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+            if pattern[p] == b'?' {
+                // ? matches one char, not one byte
+                n += utf8_len(name[n]);
+            } else {
+                n += 1;
+            }
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            // backtracking, the last * consumes one more char
+            let next = sn + utf8_len(name[sn]);
+            star = Some((sp, next));
+            p = sp + 1;
+            n = next;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first {
+        b if b < 0x80 => 1,
+        b if b >= 0xf0 => 4,
+        b if b >= 0xe0 => 3,
+        _ => 2,
+    }
+}
+
+/// Literal prefix of a regex anchored with `^`
+fn regex_anchor(re: &str) -> &str {
+    // with alternatives the literal could be in only one of the branches
+    if !re.starts_with('^') || re.contains('|') {
+        return "";
+    }
+
+    let meta = |c: char| "\\.+*?()|[]{}^$".contains(c);
+    let lit = &re[1..];
+    let end = lit.find(meta).unwrap_or(lit.len());
+
+    // a quantifier applies to the last literal char, so it's optional
+    match lit[end..].chars().next() {
+        Some('?') | Some('*') | Some('{') => {
+            let last = lit[..end].char_indices().last().map(|(i, _)| i).unwrap_or(0);
+            &lit[..last]
+        }
+        _ => &lit[..end],
+    }
+}
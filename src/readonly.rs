@@ -0,0 +1,86 @@
+use anyhow::Error;
+
+use std::fmt;
+
+use crate::store::Store;
+use crate::store::Continue;
+
+/// Error returned by the writes to a read-only store. The error can be
+/// checked with `err.downcast_ref::<ReadOnlyError>()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadOnlyError {
+    /// rejected operation, "push" or "rm"
+    pub op: &'static str,
+    pub db: String,
+    pub key: String,
+}
+
+impl fmt::Display for ReadOnlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "read-only store, {} {}:{}", self.op, self.db, self.key)
+    }
+}
+
+impl std::error::Error for ReadOnlyError {}
+
+impl ReadOnlyError {
+    pub(crate) fn new(op: &'static str, db: &str, key: &str) -> ReadOnlyError {
+        ReadOnlyError { op, db: db.to_string(), key: key.to_string() }
+    }
+}
+
+/// Store wrapper that rejects the writes with a `ReadOnlyError`, so any
+/// store can be given to code that should only read it. The reads go to
+/// the wrapped store
+pub struct ReadOnly<S> {
+    store: S,
+}
+
+impl<S: Store> ReadOnly<S> {
+    pub fn new(store: S) -> ReadOnly<S> {
+        ReadOnly { store }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: Store> Store for ReadOnly<S> {
+    fn push(&self, db: &'static str, key: &str, _value: Vec<u8>)
+        -> Result<(), Error> {
+        Err(ReadOnlyError::new("push", db, key).into())
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.store.pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.store.iter(db, prefix, f)
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.store.iter_kv(db, prefix, f)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.store.iter_kv_after(db, prefix, after, f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        Err(ReadOnlyError::new("rm", db, key).into())
+    }
+}
@@ -0,0 +1,765 @@
+//! Network store, to share a store between processes or machines.
+//!
+//! A `Server` exposes any `Store` and a `SignalerAsync` over TCP or a unix
+//! socket, and the `RemoteStore` client implements `Store` and `Signaler`,
+//! so models can be stored and signals emitted and received in the server
+//! like in a local store:
+//!
+//! ```ignore
+//! // server, or the mdl-server binary
+//! let server = Server::new(Cache::new("/var/lib/app.lmdb").unwrap());
+//! let listener = server.listen_tcp("0.0.0.0:7878").unwrap();
+//!
+//! // clients
+//! let store = RemoteStore::connect_tcp("server:7878").unwrap();
+//! let _sub = A::subscribe(&store, "a", |change| { ... }).unwrap();
+//! a.store_sig(&store, &store).unwrap();
+//! ```
+//!
+//! The protocol is a sequence of frames, each frame is the length as a u32
+//! little endian followed by a bincode encoded `Request` or `Response`.
+
+use anyhow::Error;
+use anyhow::anyhow;
+use bincode::{serialize, deserialize};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Sender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use crate::cache::is_not_found;
+use crate::store::Store;
+use crate::store::Continue;
+use crate::pattern::Pattern;
+use crate::readonly::ReadOnlyError;
+use crate::signal::{SigBase, Signal, Signaler, SignalerAsync, Subscription};
+
+/// Frames bigger than this are treated as a protocol error. The frames are
+/// read as the data comes, so a big length doesn't allocate anything
+const MAX_FRAME: u32 = 16 * 1024 * 1024;
+/// Number of objects of each `Iter` request
+const ITER_PAGE: u32 = 512;
+/// Size of the objects of each `Iter` response, the page ends after the
+/// object that reaches it
+const PAGE_BYTES: usize = 4 * 1024 * 1024;
+/// Max number of different dbs, the db names are leaked to get the
+/// `&'static str` names of the `Store` methods
+const MAX_DBS: usize = 1024;
+/// Time to wait for a response
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Frames waiting to be sent to a client. Clients that don't read the
+/// signals fast enough to keep the queue under this are disconnected
+const SEND_QUEUE: usize = 1024;
+/// Max time to wait between accept errors, like running out of fds
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Request {
+    Push { id: u64, db: String, key: String, value: Vec<u8> },
+    Pull { id: u64, db: String, key: String },
+    /// objects with the prefix and a key after `after`, up to `limit`
+    Iter { id: u64, db: String, prefix: String, after: Option<String>, limit: u32 },
+    Rm { id: u64, db: String, key: String },
+    /// forward the signals that match the pattern, without response
+    Subscribe { sub: u64, pattern: WirePattern },
+    Unsubscribe { sub: u64 },
+    /// emits the signal in the server, without response
+    Emit { signal: Signal },
+}
+
+/// Objects of an `Iter` page, (key, value)
+type Items = Vec<(String, Vec<u8>)>;
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Response {
+    Ok { id: u64 },
+    Value { id: u64, value: Vec<u8> },
+    /// `more` is false if there are no more objects after the items
+    Items { id: u64, items: Items, more: bool },
+    Error { id: u64, code: ErrorCode, msg: String },
+    Signal { signal: Signal },
+}
+
+/// Kind of the store errors, so the clients can check them like the local
+/// store errors
+#[derive(Serialize, Deserialize, Debug)]
+enum ErrorCode {
+    /// missing key, returned as the LMDB not found error
+    NotFound,
+    /// `ReadOnlyError`
+    ReadOnly { op: String, db: String, key: String },
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum WirePattern {
+    Prefix(String),
+    Exact(String),
+    Glob(String),
+    Regex(String),
+}
+
+/// Socket that can be split in a reader and a writer
+trait Stream: Read + Write + Send + Sync + 'static {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>>;
+    fn close(&self);
+}
+
+impl Stream for TcpStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+type Writer = Arc<Mutex<BufWriter<Box<dyn Stream>>>>;
+
+fn write_frame<T: Serialize>(writer: &Writer, msg: &T) -> Result<(), Error> {
+    let mut w = writer.lock().map_err(|_| anyhow!("connection error"))?;
+    send_frame(&mut *w, msg)
+}
+
+fn send_frame<W: Write, T: Serialize>(w: &mut W, msg: &T) -> Result<(), Error> {
+    let data = serialize(msg)?;
+    if data.len() > MAX_FRAME as usize {
+        return Err(anyhow!("frame too big, {} bytes", data.len()));
+    }
+    w.write_all(&(data.len() as u32).to_le_bytes())?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Reads the next frame, `None` if the connection is closed
+fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>, Error> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len);
+    if len > MAX_FRAME {
+        return Err(anyhow!("frame too big, {} bytes", len));
+    }
+    let mut data = vec![];
+    reader.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len as usize {
+        return Err(anyhow!("connection closed in the middle of a frame"));
+    }
+    Ok(Some(deserialize(&data)?))
+}
+
+impl From<&Pattern> for WirePattern {
+    fn from(p: &Pattern) -> WirePattern {
+        match p {
+            Pattern::Prefix(p) => WirePattern::Prefix(p.clone()),
+            Pattern::Exact(p) => WirePattern::Exact(p.clone()),
+            Pattern::Glob(p) => WirePattern::Glob(p.clone()),
+            Pattern::Regex(r) => WirePattern::Regex(r.as_str().to_string()),
+        }
+    }
+}
+
+impl ErrorCode {
+    fn new(err: &Error) -> ErrorCode {
+        if let Some(e) = err.downcast_ref::<ReadOnlyError>() {
+            return ErrorCode::ReadOnly { op: e.op.to_string(), db: e.db.clone(), key: e.key.clone() };
+        }
+        // the stores without LMDB return "Not found" errors
+        match is_not_found(err) || err.to_string().starts_with("Not found") {
+            true => ErrorCode::NotFound,
+            false => ErrorCode::Other,
+        }
+    }
+
+    /// The error of the code, like the error of a local store
+    fn error(self, msg: String) -> Error {
+        match self {
+            ErrorCode::NotFound => Error::new(lmdb::Error::NotFound).context(msg),
+            ErrorCode::ReadOnly { op, db, key } => {
+                let op = match op.as_str() {
+                    "rm" => "rm",
+                    _ => "push",
+                };
+                ReadOnlyError::new(op, &db, &key).into()
+            }
+            ErrorCode::Other => anyhow!(msg),
+        }
+    }
+}
+
+impl WirePattern {
+    fn pattern(self) -> Result<Pattern, Error> {
+        match self {
+            WirePattern::Prefix(p) => Ok(Pattern::Prefix(p)),
+            WirePattern::Exact(p) => Ok(Pattern::Exact(p)),
+            WirePattern::Glob(p) => Ok(Pattern::Glob(p)),
+            WirePattern::Regex(r) => Pattern::regex(&r),
+        }
+    }
+}
+
+// Server
+
+/// Exposes a store and a signaler to the `RemoteStore` clients. This struct
+/// implements clone, all the clones share the store and the signaler
+pub struct Server<S> {
+    store: Arc<S>,
+    sig: SignalerAsync,
+    dbs: Arc<Mutex<HashSet<&'static str>>>,
+}
+
+/// Accepts the connections in a thread. The thread stops and the open
+/// connections are closed when the listener drops
+pub struct Listener {
+    addr: String,
+    stopped: Arc<AtomicBool>,
+    conns: Arc<Mutex<HashMap<u64, Box<dyn Stream>>>>,
+    wake: Box<dyn Fn() + Send>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: Store + Send + Sync + 'static> Server<S> {
+    /// Creates the server with a started `SignalerAsync`
+    pub fn new(store: S) -> Result<Server<S>, Error> {
+        let sig = SignalerAsync::new();
+        sig.start()?;
+        Ok(Server::with_signaler(store, sig))
+    }
+
+    /// Creates the server with the signaler, the signals emitted in this
+    /// signaler are sent to the clients
+    pub fn with_signaler(store: S, sig: SignalerAsync) -> Server<S> {
+        Server { store: Arc::new(store), sig, dbs: Arc::new(Mutex::new(HashSet::new())) }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn signaler(&self) -> &SignalerAsync {
+        &self.sig
+    }
+
+    /// Listens in the TCP address, like "127.0.0.1:7878". Port 0 picks a
+    /// free port, see `Listener::addr`
+    pub fn listen_tcp(&self, addr: &str) -> Result<Listener, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?.to_string();
+        let wake_addr = addr.clone();
+        let wake = Box::new(move || { let _ = TcpStream::connect(&wake_addr); });
+        self.accept(addr, wake, move || {
+            let (stream, _) = listener.accept()?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream) as Box<dyn Stream>)
+        })
+    }
+
+    /// Listens in the unix socket path. A socket file without a server
+    /// listening is replaced, other files are never removed
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<Path>>(&self, path: P) -> Result<Listener, Error> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let path = path.as_ref().to_path_buf();
+        if let Ok(meta) = std::fs::symlink_metadata(&path) {
+            if !meta.file_type().is_socket() {
+                return Err(anyhow!("{} exists and it's not a socket", path.display()));
+            }
+            if UnixStream::connect(&path).is_err() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        let listener = UnixListener::bind(&path)?;
+        let wake_path = path.clone();
+        let wake = Box::new(move || {
+            let _ = UnixStream::connect(&wake_path);
+            let _ = std::fs::remove_file(&wake_path);
+        });
+        self.accept(path.display().to_string(), wake, move || {
+            let (stream, _) = listener.accept()?;
+            Ok(Box::new(stream) as Box<dyn Stream>)
+        })
+    }
+
+    fn accept<A>(&self, addr: String, wake: Box<dyn Fn() + Send>, accept: A)
+        -> Result<Listener, Error>
+        where A: Fn() -> Result<Box<dyn Stream>, Error> + Send + 'static {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let conns = Arc::new(Mutex::new(HashMap::new()));
+        let server = self.clone();
+        let (s, c) = (stopped.clone(), conns.clone());
+        let thread = thread::Builder::new()
+            .name("mdl-server".to_string())
+            .spawn(move || {
+                let mut next = 0;
+                let mut backoff = Duration::from_millis(0);
+                loop {
+                    let stream = accept();
+                    if s.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    // a failed connection doesn't stop the server, but the
+                    // errors can repeat, like without free fds
+                    match stream.and_then(|stream| {
+                        next += 1;
+                        server.serve(next, stream, c.clone())
+                    }) {
+                        Ok(()) => backoff = Duration::from_millis(0),
+                        Err(_) => {
+                            backoff = (backoff * 2).clamp(Duration::from_millis(10), MAX_BACKOFF);
+                            thread::sleep(backoff);
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Listener { addr, stopped, conns, wake, thread: Some(thread) })
+    }
+
+    /// Handles a connection in a thread. The responses and signals are
+    /// queued and written by other thread, so a slow client doesn't block
+    /// the signaler loop
+    fn serve(&self, n: u64, stream: Box<dyn Stream>,
+             conns: Arc<Mutex<HashMap<u64, Box<dyn Stream>>>>) -> Result<(), Error> {
+        let reader = stream.try_clone_stream()?;
+        let closer = stream.try_clone_stream()?;
+        let slow = stream.try_clone_stream()?;
+        // `None` stops the writer
+        let (tx, rx) = sync_channel::<Option<Response>>(SEND_QUEUE);
+
+        thread::Builder::new()
+            .name(format!("mdl-conn-{}-w", n))
+            .spawn(move || {
+                let mut writer = BufWriter::new(stream);
+                while let Ok(Some(resp)) = rx.recv() {
+                    if send_frame(&mut writer, &resp).is_err() {
+                        writer.get_ref().close();
+                        break;
+                    }
+                }
+            })?;
+        lock(&conns).insert(n, closer);
+
+        let server = self.clone();
+        thread::Builder::new()
+            .name(format!("mdl-conn-{}", n))
+            .spawn(move || {
+                let patterns = Arc::new(Mutex::new(HashMap::new()));
+                let (t, p) = (tx.clone(), patterns.clone());
+                // one forwarder per connection, so each signal is sent once
+                let _forward = server.sig.subscribe("", Box::new(move |signal| {
+                    let matches = lock(&p).values().any(|p: &Pattern| p.matches(&signal.name));
+                    if !matches {
+                        return;
+                    }
+                    // the client doesn't read the signals, dropping some
+                    // signals would leave it out of sync
+                    if let Err(TrySendError::Full(_)) = t.try_send(Some(Response::Signal { signal })) {
+                        slow.close();
+                    }
+                }));
+
+                let mut reader = BufReader::new(reader);
+                while let Ok(Some(req)) = read_frame::<_, Request>(&mut reader) {
+                    let resp = server.handle(req, &patterns);
+                    if let Some(resp) = resp {
+                        if tx.send(Some(resp)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                if let Some(stream) = lock(&conns).remove(&n) {
+                    stream.close();
+                }
+                let _ = tx.send(None);
+            })?;
+        Ok(())
+    }
+
+    fn handle(&self, req: Request, patterns: &Mutex<HashMap<u64, Pattern>>) -> Option<Response> {
+        let (id, r) = match req {
+            Request::Push { id, db, key, value } => {
+                (id, self.db(db).and_then(|db| self.store.push(db, &key, value))
+                        .map(|_| Response::Ok { id }))
+            }
+            Request::Pull { id, db, key } => {
+                (id, self.db(db).and_then(|db| self.store.pull(db, &key, |v| Ok(v.to_vec())))
+                        .map(|value| Response::Value { id, value }))
+            }
+            Request::Iter { id, db, prefix, after, limit } => {
+                (id, self.db(db).and_then(|db| self.page(db, &prefix, after, limit))
+                        .map(|(items, more)| Response::Items { id, items, more }))
+            }
+            Request::Rm { id, db, key } => {
+                (id, self.db(db).and_then(|db| self.store.rm(db, &key))
+                        .map(|_| Response::Ok { id }))
+            }
+            Request::Subscribe { sub, pattern } => {
+                // an invalid pattern is checked by the client
+                if let Ok(pattern) = pattern.pattern() {
+                    lock(patterns).insert(sub, pattern);
+                }
+                return None;
+            }
+            Request::Unsubscribe { sub } => {
+                lock(patterns).remove(&sub);
+                return None;
+            }
+            Request::Emit { signal } => {
+                let _ = self.sig.emit_signal(signal);
+                return None;
+            }
+        };
+        // a big value can't be sent, the client gets an error instead
+        let r = r.and_then(|resp| match bincode::serialized_size(&resp)? {
+            n if n > MAX_FRAME as u64 => Err(anyhow!("response too big, {} bytes", n)),
+            _ => Ok(resp),
+        });
+        Some(r.unwrap_or_else(|e| Response::Error { id, code: ErrorCode::new(&e), msg: e.to_string() }))
+    }
+
+    /// Objects of the page, up to `limit` objects or `PAGE_BYTES`, and if
+    /// there can be more objects. The next pages start from the last key of the
+    /// previous one
+    fn page(&self, db: &'static str, prefix: &str, after: Option<String>, limit: u32)
+        -> Result<(Items, bool), Error> {
+        let items = RefCell::new(vec![]);
+        let bytes = RefCell::new(0);
+        let more = RefCell::new(false);
+        let add = |k: &str, v: &[u8]| {
+            let mut items = items.borrow_mut();
+            let mut bytes = bytes.borrow_mut();
+            // the object goes to the next page, a big object goes alone
+            if !items.is_empty() && *bytes + k.len() + v.len() > PAGE_BYTES {
+                *more.borrow_mut() = true;
+                return Continue(false);
+            }
+            items.push((k.to_string(), v.to_vec()));
+            *bytes += k.len() + v.len();
+            let full = items.len() >= limit as usize || *bytes >= PAGE_BYTES;
+            *more.borrow_mut() = full;
+            Continue(!full)
+        };
+        match after {
+            Some(ref after) => self.store.iter_kv_after(db, prefix, after, add)?,
+            None => self.store.iter_kv(db, prefix, add)?,
+        }
+        Ok((items.into_inner(), more.into_inner()))
+    }
+
+    /// The static name of the db
+    fn db(&self, db: String) -> Result<&'static str, Error> {
+        let mut dbs = lock(&self.dbs);
+        if let Some(name) = dbs.get(db.as_str()) {
+            return Ok(name);
+        }
+        if dbs.len() >= MAX_DBS {
+            return Err(anyhow!("too many dbs, {}", db));
+        }
+        let name: &'static str = Box::leak(db.into_boxed_str());
+        dbs.insert(name);
+        Ok(name)
+    }
+}
+
+// the store doesn't need to be Clone
+impl<S> Clone for Server<S> {
+    fn clone(&self) -> Server<S> {
+        Server { store: self.store.clone(), sig: self.sig.clone(), dbs: self.dbs.clone() }
+    }
+}
+
+impl Listener {
+    /// The listening address, the TCP address with the port or the socket
+    /// path
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Blocks until the listener is stopped from other thread
+    pub fn join(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Stops accepting connections and closes the open connections
+    pub fn stop(mut self) {
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        (self.wake)();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        for (_, stream) in lock(&self.conns).drain() {
+            stream.close();
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.close();
+        }
+    }
+}
+
+// Client
+
+/// Client of a `Server`, implements `Store` and `Signaler`. The signals
+/// received from the server are dispatched by a local `SignalerAsync`, so
+/// the callbacks can use the store.
+///
+/// This struct implements clone, all the clones share the connection, that
+/// is closed when the last clone drops.
+#[derive(Clone)]
+pub struct RemoteStore {
+    inner: Arc<Client>,
+}
+
+struct Client {
+    writer: Writer,
+    closer: Box<dyn Stream>,
+    next: AtomicU64,
+    pending: Arc<Mutex<HashMap<u64, Sender<Response>>>>,
+    connected: Arc<AtomicBool>,
+    local: SignalerAsync,
+}
+
+/// Sends the `Unsubscribe` request when the local subscription callback is
+/// dropped
+struct RemoteSub {
+    sub: u64,
+    writer: Writer,
+}
+
+impl RemoteStore {
+    pub fn connect_tcp(addr: &str) -> Result<RemoteStore, Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        RemoteStore::connect(Box::new(stream))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<RemoteStore, Error> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        RemoteStore::connect(Box::new(UnixStream::connect(path)?))
+    }
+
+    fn connect(stream: Box<dyn Stream>) -> Result<RemoteStore, Error> {
+        let reader = stream.try_clone_stream()?;
+        let closer = stream.try_clone_stream()?;
+        let writer: Writer = Arc::new(Mutex::new(BufWriter::new(stream)));
+        let pending: Arc<Mutex<HashMap<u64, Sender<Response>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let connected = Arc::new(AtomicBool::new(true));
+        let local = SignalerAsync::new();
+        local.start()?;
+
+        let (p, c, sig) = (pending.clone(), connected.clone(), local.clone());
+        thread::Builder::new()
+            .name("mdl-remote".to_string())
+            .spawn(move || {
+                let mut reader = BufReader::new(reader);
+                while let Ok(Some(resp)) = read_frame::<_, Response>(&mut reader) {
+                    let id = match resp {
+                        Response::Signal { signal } => {
+                            let _ = sig.emit_signal(signal);
+                            continue;
+                        }
+                        Response::Ok { id } | Response::Value { id, .. } |
+                        Response::Items { id, .. } | Response::Error { id, .. } => id,
+                    };
+                    if let Some(tx) = lock(&p).remove(&id) {
+                        let _ = tx.send(resp);
+                    }
+                }
+                // the waiting requests fail
+                c.store(false, Ordering::SeqCst);
+                lock(&p).clear();
+            })?;
+
+        let client = Client { writer, closer, next: AtomicU64::new(1), pending, connected, local };
+        Ok(RemoteStore { inner: Arc::new(client) })
+    }
+
+    /// Subscribes the callback to the signals that match the pattern, emitted
+    /// in the server by any client
+    pub fn subscribe<P: Into<Pattern>>(&self, signal: P, f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+        self.connect(signal.into(), f)
+    }
+
+    /// Returns false if the connection is closed
+    pub fn is_connected(&self) -> bool {
+        self.inner.connected.load(Ordering::SeqCst)
+    }
+
+    fn call<F>(&self, req: F) -> Result<Response, Error>
+        where F: FnOnce(u64) -> Request {
+        if !self.is_connected() {
+            return Err(anyhow!("connection closed"));
+        }
+        let id = self.inner.next.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = channel();
+        lock(&self.inner.pending).insert(id, tx);
+        if let Err(err) = write_frame(&self.inner.writer, &req(id)) {
+            lock(&self.inner.pending).remove(&id);
+            return Err(err);
+        }
+
+        match rx.recv_timeout(TIMEOUT) {
+            Ok(Response::Error { code, msg, .. }) => Err(code.error(msg)),
+            Ok(resp) => Ok(resp),
+            Err(_) => {
+                lock(&self.inner.pending).remove(&id);
+                Err(anyhow!("connection closed"))
+            }
+        }
+    }
+
+    /// Requests the pages of objects with the prefix after the key `after`
+    fn pages<F>(&self, db: &'static str, prefix: &str, mut after: Option<String>, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        loop {
+            let (db, prefix, a) = (db.to_string(), prefix.to_string(), after.take());
+            let (items, more) = match self.call(|id| Request::Iter { id, db, prefix, after: a, limit: ITER_PAGE })? {
+                Response::Items { items, more, .. } => (items, more),
+                _ => return Err(anyhow!("unexpected response")),
+            };
+            for (k, v) in items {
+                if let Continue(false) = f(&k, &v) {
+                    return Ok(());
+                }
+                after = Some(k);
+            }
+            if !more {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.closer.close();
+        self.local.stop();
+    }
+}
+
+impl Drop for RemoteSub {
+    fn drop(&mut self) {
+        let _ = write_frame(&self.writer, &Request::Unsubscribe { sub: self.sub });
+    }
+}
+
+impl Store for RemoteStore {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        let (db, key) = (db.to_string(), key.to_string());
+        self.call(|id| Request::Push { id, db, key, value })?;
+        Ok(())
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        let (db, key) = (db.to_string(), key.to_string());
+        match self.call(|id| Request::Pull { id, db, key })? {
+            Response::Value { value, .. } => formatter(&value),
+            _ => Err(anyhow!("unexpected response")),
+        }
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.pages(db, prefix, None, f)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.pages(db, prefix, Some(after.to_string()), f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        let (db, key) = (db.to_string(), key.to_string());
+        self.call(|id| Request::Rm { id, db, key })?;
+        Ok(())
+    }
+}
+
+impl Signaler for RemoteStore {
+    fn base(&self) -> &SigBase {
+        self.inner.local.base()
+    }
+
+    /// Subscribes the callback in the local signaler and asks the server to
+    /// send the signals that match the pattern
+    fn connect(&self, pattern: Pattern, f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+        let sub = self.inner.next.fetch_add(1, Ordering::SeqCst);
+        let wire = WirePattern::from(&pattern);
+        write_frame(&self.inner.writer, &Request::Subscribe { sub, pattern: wire })?;
+
+        let remote = RemoteSub { sub, writer: self.inner.writer.clone() };
+        self.inner.local.connect(pattern, Box::new(move |signal| {
+            let _ = &remote;
+            f(signal)
+        }))
+    }
+
+    /// Emits the signal in the server, that sends it to all the subscribed
+    /// clients
+    fn emit_signal(&self, signal: Signal) -> Result<(), Error> {
+        write_frame(&self.inner.writer, &Request::Emit { signal })
+    }
+
+    fn is_running(&self) -> bool {
+        self.is_connected() && self.inner.local.is_running()
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
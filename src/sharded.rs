@@ -0,0 +1,366 @@
+use anyhow::Error;
+use anyhow::anyhow;
+use bincode::{serialize, deserialize};
+use crc32fast::hash as crc32;
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use crate::cache::{Cache, is_not_found};
+use crate::store::Store;
+use crate::store::Continue;
+
+/// File with the layout of the shards, in the `ShardedStore::open` path
+const LAYOUT_FILE: &str = "mdl-shards";
+/// Objects read from each shard at once by the `Partition::Hash` iteration
+const MERGE_BATCH: usize = 256;
+
+/// How the keys are assigned to the shards
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Partition {
+    /// by the key hash, the keys are evenly distributed
+    Hash,
+    /// by key ranges, with the first key of each shard after the first one.
+    /// The prefix iteration doesn't need to sort the keys
+    Range(Vec<String>),
+}
+
+/// Store that spreads the keys across several stores, so the writes to
+/// different shards don't wait for each other. Each shard is usually a LMDB
+/// `Cache` in its own directory, see `ShardedStore::open`.
+///
+/// `push`, `pull` and `rm` go to the key shard. The prefix iteration merges
+/// the shards in key order, with `Partition::Hash` the shards are read in
+/// batches that are merged, so only a few objects of each shard are in
+/// memory.
+pub struct ShardedStore<S> {
+    shards: Vec<S>,
+    partition: Partition,
+}
+
+impl<S: Store> ShardedStore<S> {
+    pub fn new(shards: Vec<S>, partition: Partition) -> Result<ShardedStore<S>, Error> {
+        if shards.is_empty() {
+            return Err(anyhow!("a sharded store needs at least one shard"));
+        }
+        if let Partition::Range(ref splits) = partition {
+            if splits.len() != shards.len() - 1 {
+                return Err(anyhow!("{} shards need {} range splits, not {}",
+                                   shards.len(), shards.len() - 1, splits.len()));
+            }
+            if splits.windows(2).any(|w| w[0] >= w[1]) {
+                return Err(anyhow!("the range splits must be sorted"));
+            }
+        }
+        Ok(ShardedStore { shards, partition })
+    }
+
+    pub fn shards(&self) -> &[S] {
+        &self.shards
+    }
+
+    pub fn partition(&self) -> &Partition {
+        &self.partition
+    }
+
+    /// Index of the shard of the key
+    pub fn shard(&self, db: &str, key: &str) -> usize {
+        match self.partition {
+            Partition::Hash => {
+                // crc32 is stable, the same key goes to the same shard
+                let h = crc32(format!("{}:{}", db, key).as_bytes());
+                h as usize % self.shards.len()
+            }
+            Partition::Range(ref splits) => {
+                splits.iter().take_while(|s| s.as_str() <= key).count()
+            }
+        }
+    }
+
+    /// Copies all the objects of the `dbs` to the `to` store. Returns the
+    /// number of copied objects
+    pub fn copy_to<T: Store>(&self, to: &T, dbs: &[&'static str]) -> Result<usize, Error> {
+        let n = RefCell::new(0);
+        let err = RefCell::new(None);
+        for db in dbs {
+            self.iter_kv(db, "", |k, v| {
+                match to.push(db, k, v.to_vec()) {
+                    Ok(()) => { *n.borrow_mut() += 1; Continue(true) }
+                    Err(e) => { *err.borrow_mut() = Some(e); Continue(false) }
+                }
+            }).or_else(ignore_not_found)?;
+            if let Some(e) = err.borrow_mut().take() {
+                return Err(e);
+            }
+        }
+        Ok(n.into_inner())
+    }
+
+    /// Reads the next batch of the shard after the `cursor` last key
+    fn next_batch(&self, shard: usize, db: &'static str, prefix: &str, cursor: &mut Cursor)
+        -> Result<(), Error> {
+        let batch = RefCell::new(VecDeque::new());
+        let add = |k: &str, v: &[u8]| {
+            let mut batch = batch.borrow_mut();
+            batch.push_back((k.to_string(), v.to_vec()));
+            Continue(batch.len() < MERGE_BATCH)
+        };
+        let r = match cursor.last {
+            Some(ref last) => self.shards[shard].iter_kv_after(db, prefix, last, add),
+            None => self.shards[shard].iter_kv(db, prefix, add),
+        };
+
+        let batch = batch.into_inner();
+        cursor.done = batch.len() < MERGE_BATCH;
+        if let Some((k, _)) = batch.back() {
+            cursor.last = Some(k.clone());
+        }
+        cursor.items = batch;
+        r
+    }
+
+    /// Shards where the keys with the prefix can be
+    fn prefix_shards(&self, prefix: &str) -> Vec<usize> {
+        match self.partition {
+            Partition::Hash => (0..self.shards.len()).collect(),
+            Partition::Range(ref splits) => {
+                let first = self.shard("", prefix);
+                // the last shard with a split that can start with the prefix
+                let last = splits.iter()
+                    .take_while(|s| s.as_str() <= prefix || s.starts_with(prefix))
+                    .count();
+                (first..=last).collect()
+            }
+        }
+    }
+}
+
+impl ShardedStore<Cache> {
+    /// Opens `n` LMDB shards, in the `g<generation>/shard-<i>` directories
+    /// of the `path`. The layout is stored in the path, and opening it with
+    /// a different layout fails, use `reshard` to change it
+    pub fn open(path: &str, n: usize, partition: Partition) -> Result<ShardedStore<Cache>, Error> {
+        fs::create_dir_all(path)?;
+        let (gen, stored) = match read_layout(path)? {
+            Some(layout) => {
+                if layout.shards != n || layout.partition != partition {
+                    return Err(anyhow!("{} has {} shards with {:?} partition, reshard it first",
+                                       path, layout.shards, layout.partition));
+                }
+                (layout.gen, true)
+            }
+            None => (0, false),
+        };
+
+        let store = open_shards(path, gen, n, partition)?;
+        if !stored {
+            write_layout(path, &Layout { gen, shards: n, partition: store.partition.clone() })?;
+        }
+        Ok(store)
+    }
+}
+
+/// Position of the `Partition::Hash` iteration in a shard
+#[derive(Default)]
+struct Cursor {
+    /// read objects not given to the iteration function yet
+    items: VecDeque<(String, Vec<u8>)>,
+    /// last read key, the next batch starts after it
+    last: Option<String>,
+    /// all the objects are read
+    done: bool,
+}
+
+/// Stored layout of a sharded store. The shards are in the `gen`
+/// directory, `reshard` writes the new shards in the next one
+#[derive(Serialize, Deserialize)]
+struct Layout {
+    gen: u64,
+    shards: usize,
+    partition: Partition,
+}
+
+/// Changes the number of shards or the partition of the sharded store in
+/// `path`, moving the objects of all the dbs. The store must not be open
+/// while it's resharded. Returns the number of moved objects
+///
+/// The new shards are written in a new directory, and the store only uses
+/// them once the new layout file replaces the old one. If the process dies
+/// before that the old shards are still used, and the next `reshard` starts
+/// again.
+pub fn reshard(path: &str, n: usize, partition: Partition) -> Result<usize, Error> {
+    let old = read_layout(path)?.ok_or_else(|| anyhow!("{} is not a sharded store", path))?;
+    let gen = old.gen + 1;
+
+    // leftovers of interrupted reshards, the layout doesn't use them
+    for entry in fs::read_dir(path)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('g') && name != gen_dir(old.gen) && name[1..].parse::<u64>().is_ok() {
+            fs::remove_dir_all(Path::new(path).join(&*name))?;
+        }
+    }
+
+    let moved = {
+        let from = open_shards(path, old.gen, old.shards, old.partition)?;
+        let to = open_shards(path, gen, n, partition.clone())?;
+
+        let mut dbs = BTreeSet::new();
+        for shard in from.shards() {
+            dbs.extend(shard.dbs()?);
+        }
+        // the db names live until the end of the program, they are few
+        let dbs: Vec<&'static str> = dbs.into_iter()
+            .map(|db| &*Box::leak(db.into_boxed_str()))
+            .collect();
+        let moved = from.copy_to(&to, &dbs)?;
+        for shard in to.shards() {
+            shard.env.sync(true)?;
+        }
+        moved
+    };
+
+    write_layout(path, &Layout { gen, shards: n, partition })?;
+    fs::remove_dir_all(Path::new(path).join(gen_dir(old.gen)))?;
+    Ok(moved)
+}
+
+fn open_shards(path: &str, gen: u64, n: usize, partition: Partition)
+    -> Result<ShardedStore<Cache>, Error> {
+    let shards = (0..n)
+        .map(|i| Cache::new(&format!("{}/{}/shard-{}", path, gen_dir(gen), i)))
+        .collect::<Result<Vec<_>, _>>()?;
+    ShardedStore::new(shards, partition)
+}
+
+fn gen_dir(gen: u64) -> String {
+    format!("g{}", gen)
+}
+
+/// Reads the layout file, `None` if there's no layout file
+fn read_layout(path: &str) -> Result<Option<Layout>, Error> {
+    let file = Path::new(path).join(LAYOUT_FILE);
+    match fs::read(&file) {
+        Ok(data) => {
+            let layout = deserialize(&data)
+                .map_err(|e| anyhow!("invalid layout file {}, {}", file.display(), e))?;
+            Ok(Some(layout))
+        }
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!("error reading {}, {}", file.display(), e)),
+    }
+}
+
+/// Replaces the layout file, writing a temporary file that is renamed
+fn write_layout(path: &str, layout: &Layout) -> Result<(), Error> {
+    let dir = Path::new(path);
+    let tmp = dir.join(format!("{}.tmp", LAYOUT_FILE));
+    let mut f = File::create(&tmp)?;
+    f.write_all(&serialize(layout)?)?;
+    f.sync_all()?;
+    fs::rename(&tmp, dir.join(LAYOUT_FILE))?;
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Ignores the not found error, LMDB returns it if there's no key with the
+/// prefix, that is normal for a shard
+fn ignore_not_found(err: Error) -> Result<(), Error> {
+    match is_not_found(&err) {
+        true => Ok(()),
+        false => Err(err),
+    }
+}
+
+impl<S: Store> Store for ShardedStore<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.shards[self.shard(db, key)].push(db, key, value)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.shards[self.shard(db, key)].pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let shards = self.prefix_shards(prefix);
+        let mut found = false;
+        let mut last_err = None;
+
+        if let Partition::Range(_) = self.partition {
+            // the shards are in key order
+            let stopped = RefCell::new(false);
+            for i in shards {
+                let r = self.shards[i].iter_kv(db, prefix, |k, v| {
+                    let c = f(k, v);
+                    *stopped.borrow_mut() = !c.0;
+                    c
+                });
+                match r {
+                    Ok(()) => found = true,
+                    Err(e) if is_not_found(&e) => last_err = Some(e),
+                    Err(e) => return Err(e),
+                }
+                if *stopped.borrow() {
+                    return Ok(());
+                }
+            }
+        } else {
+            // k-way merge of the shards, each shard is in key order
+            let mut cursors = vec![];
+            for i in shards {
+                let mut cursor = Cursor::default();
+                match self.next_batch(i, db, prefix, &mut cursor) {
+                    Ok(()) => found = true,
+                    Err(e) if is_not_found(&e) => last_err = Some(e),
+                    Err(e) => return Err(e),
+                }
+                cursors.push((i, cursor));
+            }
+
+            loop {
+                let next = cursors.iter()
+                    .enumerate()
+                    .filter_map(|(n, (_, c))| c.items.front().map(|(k, _)| (k, n)))
+                    .min()
+                    .map(|(_, n)| n);
+                let (i, cursor) = match next {
+                    Some(n) => &mut cursors[n],
+                    None => break,
+                };
+
+                if let Some((k, v)) = cursor.items.pop_front() {
+                    if let Continue(false) = f(&k, &v) {
+                        break;
+                    }
+                }
+                if cursor.items.is_empty() && !cursor.done {
+                    self.next_batch(*i, db, prefix, cursor).or_else(ignore_not_found)?;
+                }
+            }
+        }
+        match (found, last_err) {
+            // like the shards, if no shard has the prefix
+            (false, Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.shards[self.shard(db, key)].rm(db, key)
+    }
+}
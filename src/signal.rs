@@ -1,76 +1,283 @@
 use anyhow::Error;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use std::sync::mpsc::{channel, Sender, Receiver};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::collections::HashMap;
+use std::sync::{Arc, Weak, Mutex, MutexGuard, Condvar};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle, ThreadId};
 use std::fmt;
-use std::sync::mpsc::TryRecvError;
+use std::error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::pattern::Dispatcher;
+use crate::pattern::Pattern;
+use crate::store::Continue;
+#[cfg(unix)]
+use crate::notify::Notifier;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+#[cfg(feature = "async")]
+use futures_channel::mpsc::{unbounded, UnboundedReceiver};
 
 
 macro_rules! subscribe {
-    ($self: expr, $CallBack: ident, $signal: expr, $f: expr) => {{
-        let id = *$self.base.id.lock().unwrap();
-        *$self.base.id.lock().unwrap() += 1;
+    ($self: expr, $CallBack: ident, $signal: expr, $f: expr, $delay: expr, $once: expr) => {{
+        subscribe!($self, $CallBack, $signal, $f, $delay, $once, 0, None)
+    }};
+    ($self: expr, $CallBack: ident, $signal: expr, $f: expr, $delay: expr, $once: expr,
+     $priority: expr, $shared: expr) => {{
+        // read and incremented under the same lock, the ids are unique
+        let id = {
+            let mut next = lock(&$self.base.id);
+            let id = *next;
+            *next += 1;
+            id
+        };
 
-        let c = $CallBack { id: id, callback: $f };
+        let c = $CallBack { id, callback: $f };
+        let mut slot = Slot::new(c, $delay, $once, $priority);
+        slot.shared = $shared;
+        let sub = Subscription::new(id, slot.alive.clone(), &$self.base.dropped);
 
-        let mut guard = $self.callbacks.lock().unwrap();
-        if guard.contains_key($signal) {
-            guard.get_mut($signal).map(|v| v.push(c));
-        } else {
-            guard.insert($signal.to_string(), vec![c]);
-        }
+        let mut guard = lock(&$self.callbacks);
+        guard.insert($signal.into(), Arc::new(slot));
 
-        Ok(id)
+        Ok(sub)
     }}
 }
 
 macro_rules! unsubscribe {
-    ($self: expr, $CallBack: ident, $id: expr) => {{
-        let mut guard = $self.callbacks.lock().unwrap();
-        for (_, ref mut v) in guard.iter_mut() {
-            let idx = v.iter().position(|cb: &$CallBack| cb.id() == $id);
-            if let Some(i) = idx {
-                v.remove(i);
-                break;
-            }
-        }
+    ($self: expr, $id: expr) => {{
+        let mut guard = lock(&$self.callbacks);
+        guard.remove($id);
     }}
 }
 
-/// signal -> [cb1, cb2, cb3, ...]
-type CBs = Arc<Mutex< HashMap<String, Vec<CallBack>> >>;
-type CBsSync = Arc<Mutex< HashMap<String, Vec<CallBackSync>> >>;
+/// pattern -> [cb1, cb2, cb3, ...]
+type CBs = Arc<Mutex< Callbacks<CallBack> >>;
+type CBsSync = Arc<Mutex< Callbacks<CallBackSync> >>;
 
 // Custom types
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SigType {
     Update,
     Delete,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Signal {
     pub type_: SigType,
     pub name: String,
+    /// database of the model that emits the signal, `None` for custom signals
+    pub db: Option<String>,
+    /// serialized value before the modification, if there was one
+    pub old: Option<Vec<u8>>,
+    /// serialized value after the modification, `None` for deletions
+    pub new: Option<Vec<u8>>,
+}
+
+/// Callback function, called with each signal or with a list of signals for
+/// batch subscriptions. `Chain` callbacks return `Continue(false)` to stop
+/// the signal propagation to the lower priority callbacks
+pub enum Handler {
+    One(Box<dyn Fn(Signal) + Send + 'static>),
+    Batch(Box<dyn Fn(Vec<Signal>) + Send + 'static>),
+    Chain(Box<dyn Fn(Signal) -> Continue + Send + 'static>),
+}
+
+pub enum HandlerSync {
+    One(Box<dyn Fn(Signal) + 'static>),
+    Batch(Box<dyn Fn(Vec<Signal>) + 'static>),
+    Chain(Box<dyn Fn(Signal) -> Continue + 'static>),
 }
 
 pub struct CallBack {
     pub id: u32,
-    pub callback: Box<dyn Fn(Signal) + Send + 'static>,
+    pub callback: Handler,
 }
 
 pub struct CallBackSync {
     pub id: u32,
-    pub callback: Box<dyn Fn(Signal) + 'static>,
+    pub callback: HandlerSync,
+}
+
+/// How the signals are delivered to a subscription
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Coalesce {
+    /// Signals with the same name in the window are collapsed and the last
+    /// one is delivered when the window ends
+    Key(Duration),
+    /// All signals in the window are delivered together
+    Batch(Duration),
+}
+
+/// Signals waiting for the coalescing window to end
+#[derive(Debug)]
+struct Delayed {
+    mode: Coalesce,
+    seq: u64,
+    /// seq -> (deadline, signal), ordered by deadline
+    pending: BTreeMap<u64, (Instant, Signal)>,
+    /// signal name -> seq, to collapse signals with the same name
+    names: HashMap<String, u64>,
+}
+
+/// Callback stored in the dispatcher. The callback is shared with the thread
+/// that calls it, so the dispatcher lock is not needed during the call
+#[derive(Debug)]
+struct Slot<T> {
+    id: u32,
+    cb: Mutex<T>,
+    delayed: Option<Mutex<Delayed>>,
+    /// false when the subscription is dropped or a once callback is called
+    alive: Arc<AtomicBool>,
+    once: bool,
+    /// higher priority callbacks are called first
+    priority: i32,
+    /// pooled callback, called in the worker pool instead of the loop
+    /// thread. It's called without the `cb` lock, so several workers can
+    /// call it at the same time
+    shared: Option<SharedCallBack>,
+}
+
+type SharedFn = Arc<dyn Fn(Signal) + Send + Sync + 'static>;
+
+/// Callback subscribed with `subscribe_pooled`
+struct SharedCallBack(SharedFn);
+
+/// Worker threads for the pooled callbacks. Signals are assigned to a worker
+/// by name, so the signals with the same name are delivered in order
+#[derive(Debug)]
+struct Pool<T> {
+    workers: Vec<Sender<Job<T>>>,
+}
+
+#[derive(Debug)]
+enum Job<T> {
+    Call(Arc<Slot<T>>, Signal),
+    /// answers when all the previous jobs are done
+    Flush(Sender<()>),
+}
+
+/// Subscribed callbacks, indexed by pattern
+#[derive(Debug)]
+struct Callbacks<T> {
+    trie: Dispatcher<Arc<Slot<T>>>,
+    /// id -> pattern anchor, to find the callback in the trie
+    index: HashMap<u32, String>,
+    /// subscriptions with coalescing, flushed when the window ends
+    delayed: BTreeMap<u32, Arc<Slot<T>>>,
+    /// ids of the dropped subscriptions, removed in the next operation
+    dropped: Arc<Mutex<Vec<u32>>>,
+    monitor: Arc<Monitor>,
+    pool: Option<Arc<Pool<T>>>,
+}
+
+thread_local! {
+    /// true in the pool worker threads
+    static IN_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Subscription guard returned by the subscribe methods. The callback is
+/// unsubscribed when this guard drops, use `detach` to keep the callback
+/// subscribed until `unsubscribe` is called with the id.
+///
+/// The guard can be sent to other threads and dropped there, the callback is
+/// removed from the signaler in the next signaler operation.
+#[must_use = "the callback is unsubscribed when the subscription drops"]
+pub struct Subscription {
+    id: u32,
+    alive: Arc<AtomicBool>,
+    dropped: Weak<Mutex<Vec<u32>>>,
+    detached: bool,
+}
+
+/// Failed signal delivery, a callback panicked while handling the signals
+#[derive(Clone, Debug)]
+pub struct DeliveryError {
+    /// subscription id of the failed callback
+    pub id: u32,
+    /// signals delivered in the failed call
+    pub signals: Vec<Signal>,
+    /// panic message
+    pub message: String,
+}
+
+/// Signaler health information, returned by the `status` method
+#[derive(Clone, Debug)]
+pub struct SignalerStatus {
+    /// true if the signaler is dispatching signals. For the `SignalerAsync`
+    /// this means that the loop thread is running
+    pub running: bool,
+    /// number of subscribed callbacks
+    pub subscriptions: usize,
+    /// signals emitted and not dispatched yet
+    pub pending: u64,
+    /// successful callback calls
+    pub delivered: u64,
+    /// callback calls that panicked
+    pub failed: u64,
+    /// last failed delivery
+    pub last_error: Option<DeliveryError>,
+}
+
+type ErrorHook = Arc<dyn Fn(&DeliveryError) + Send + Sync + 'static>;
+
+/// Delivery counters and error reporting shared by all clones of a signaler
+#[derive(Default)]
+struct Monitor {
+    running: AtomicBool,
+    emitted: AtomicU64,
+    received: AtomicU64,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    last_error: Mutex<Option<DeliveryError>>,
+    on_error: Mutex<Option<ErrorHook>>,
+}
+
+/// Message sent to the signaler loop
+#[derive(Debug)]
+enum Msg {
+    Signal(Signal),
+    /// stops the loop after dispatching the previous signals
+    Stop,
+}
+
+/// State of the loop thread
+#[derive(Debug, Default)]
+struct LoopThread {
+    handle: Option<JoinHandle<()>>,
+    id: Option<ThreadId>,
+    /// `Msg::Stop` is sent and the thread didn't exit yet
+    stopping: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct SigBase {
     id: Arc<Mutex<u32>>,
-    recv: Arc<Mutex<Receiver<Signal>>>,
-    main: Option<Sender<Signal>>,
+    recv: Arc<Mutex<Receiver<Msg>>>,
+    main: Sender<Msg>,
+    monitor: Arc<Monitor>,
+    dropped: Arc<Mutex<Vec<u32>>>,
+    /// loop thread, shared by all the clones. The condvar is notified when
+    /// the loop thread exits
+    thread: Arc<(Mutex<LoopThread>, Condvar)>,
+    /// wakes up external main loops, created on demand
+    #[cfg(unix)]
+    notifier: Arc<Mutex<Option<Notifier>>>,
 }
 
 #[derive(Clone, Debug)]
@@ -85,56 +292,251 @@ pub struct SignalerSync {
     callbacks: CBsSync,
 }
 
+/// Receives the signals that matches a pattern from the moment it's created,
+/// created with `Signaler::waiter`. The callback is unsubscribed when the
+/// waiter drops.
+pub struct Waiter<'a> {
+    rx: Receiver<Signal>,
+    _sub: Subscription,
+    /// dispatch signals in the waiting thread, for signalers without loop
+    /// thread. Returns false if the signaler is stopped
+    dispatch: Option<Box<dyn Fn(Duration) -> bool + 'a>>,
+}
+
+/// Stream of the signals that matches a pattern, created with
+/// `Signaler::stream`. The callback is unsubscribed when the stream drops.
+///
+/// This is only available with the `async` feature.
+#[cfg(feature = "async")]
+pub struct SignalStream {
+    rx: UnboundedReceiver<Signal>,
+    _sub: Subscription,
+}
+
 // Traits
 
 trait CB {
     fn id(&self) -> u32;
-    fn call(&self, sig: Signal);
+    /// Returns `Continue(false)` to stop the signal propagation
+    fn call(&self, sig: Signal) -> Continue;
+    fn call_batch(&self, sigs: Vec<Signal>);
 }
 
 pub trait Signaler {
-    fn base<'a>(&'a self) -> &'a SigBase;
+    fn base(&self) -> &SigBase;
+
+    /// subscribe a `Send` callback to the signals that matches the pattern.
+    /// This is the common subscription for all signalers, used to subscribe
+    /// when the signaler type is generic, like in `Model::subscribe`
+    fn connect(&self, pattern: Pattern, f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error>;
 
     /// emit a signal that trigger all callbacks subscribed to this signal
     fn emit(&self, t: SigType, signal: &str) -> Result<(), Error> {
-        if let Some(ref tx) = self.base().main {
-            let tx = tx.clone();
-            let n = signal.to_string();
-            thread::spawn(move || {
-                let _ = tx.send(Signal{ type_: t, name: n });
-            });
+        self.emit_signal(Signal::new(t, signal))
+    }
+
+    /// emit a signal with all the signal information
+    fn emit_signal(&self, signal: Signal) -> Result<(), Error> {
+        if self.base().main.send(Msg::Signal(signal)).is_ok() {
+            self.base().monitor.emitted.fetch_add(1, Ordering::SeqCst);
+            #[cfg(unix)]
+            if let Some(ref n) = *lock(&self.base().notifier) {
+                n.notify();
+            }
         }
         Ok(())
     }
+
+    /// Returns true if the signaler is dispatching signals
+    fn is_running(&self) -> bool {
+        self.base().monitor.running.load(Ordering::SeqCst)
+    }
+
+    /// Creates a `Waiter` for the signals that matches the pattern. Signals
+    /// emitted after this call can be waited with `Waiter::wait`, so the
+    /// waiter should be created before doing the operation that emits the
+    /// signal
+    fn waiter<P: Into<Pattern>>(&self, pattern: P) -> Result<Waiter<'_>, Error>
+        where Self: Sized {
+        let (tx, rx) = channel();
+        let sub = self.connect(pattern.into(), Box::new(move |sig| {
+            let _ = tx.send(sig);
+        }))?;
+
+        Ok(Waiter { rx, _sub: sub, dispatch: None })
+    }
+
+    /// Blocks until a signal that matches the pattern is dispatched or the
+    /// timeout is reached. Only signals emitted after this call are
+    /// received, for `SignalerSync` the signals pending in the queue are also
+    /// received because they are dispatched while waiting
+    fn wait_for<P: Into<Pattern>>(&self, pattern: P, timeout: Duration) -> Option<Signal>
+        where Self: Sized {
+        self.waiter(pattern).ok()?.wait(timeout)
+    }
+
+    /// Returns a `Stream` with all the signals that matches the pattern,
+    /// to consume signals from async code.
+    ///
+    /// This is only available with the `async` feature.
+    #[cfg(feature = "async")]
+    fn stream<P: Into<Pattern>>(&self, pattern: P) -> Result<SignalStream, Error>
+        where Self: Sized {
+        let (tx, rx) = unbounded();
+        let sub = self.connect(pattern.into(), Box::new(move |sig| {
+            let _ = tx.unbounded_send(sig);
+        }))?;
+
+        Ok(SignalStream { rx, _sub: sub })
+    }
 }
 
 // struct methods
 
+impl Signal {
+    pub fn new(type_: SigType, name: &str) -> Signal {
+        Signal { type_, name: name.to_string(), db: None, old: None, new: None }
+    }
+}
+
 impl SigBase {
     pub fn new() -> SigBase {
-        let (tx, rv) = channel::<Signal>();
-        let main = Some(tx);
+        let (main, rv) = channel::<Msg>();
         let recv = Arc::new(Mutex::new(rv));
         let id = Arc::new(Mutex::new(1));
-        SigBase{id, recv, main}
+        let monitor = Arc::new(Monitor::default());
+        let dropped = Arc::new(Mutex::new(vec![]));
+        let thread = Arc::new((Mutex::new(LoopThread::default()), Condvar::new()));
+        SigBase {
+            id, recv, main, monitor, dropped, thread,
+            #[cfg(unix)]
+            notifier: Arc::new(Mutex::new(None)),
+        }
     }
 }
 
+impl Default for SigBase {
+    fn default() -> SigBase { SigBase::new() }
+}
+
 impl SignalerAsync {
     pub fn new() -> SignalerAsync {
-        let callbacks = Arc::new(Mutex::new(HashMap::new()));
         let base = SigBase::new();
+        let callbacks = Arc::new(Mutex::new(Callbacks::new(&base)));
         SignalerAsync { base, callbacks }
     }
 
-    pub fn stop(&mut self) {
-        self.base.main = None;
+    /// Creates a signaler with a pool of `workers` threads to call the
+    /// callbacks subscribed with `subscribe_pooled`, so slow callbacks don't
+    /// delay the other callbacks. With 0 workers, the pooled callbacks are
+    /// called in the loop thread
+    pub fn with_workers(workers: usize) -> Result<SignalerAsync, Error> {
+        let sig = SignalerAsync::new();
+        if workers > 0 {
+            let pool = Pool::new(workers, sig.base.monitor.clone())?;
+            lock(&sig.callbacks).pool = Some(Arc::new(pool));
+        }
+        Ok(sig)
+    }
+
+    /// Starts the loop thread that dispatches the signals. Starting a
+    /// running signaler does nothing, and a stopped signaler can be started
+    /// again, the signals emitted while stopped are dispatched then.
+    ///
+    /// If the signaler is stopping, this waits for the old loop thread to
+    /// exit. Called from a callback or a pool worker of a stopping signaler
+    /// it fails instead, because the loop can't exit while it waits
+    pub fn start(&self) -> Result<(), Error> {
+        let (ref state, ref exited) = *self.base.thread;
+        let mut t = lock(state);
+        if t.stopping {
+            if t.id == Some(thread::current().id()) || IN_WORKER.with(|w| w.get()) {
+                return Err(anyhow!("the signaler is stopping, it can't be started from a callback"));
+            }
+            t = exited.wait_while(t, |t| t.stopping).unwrap_or_else(|e| e.into_inner());
+        }
+        if t.handle.as_ref().map(|h| !h.is_finished()).unwrap_or(false) {
+            return Ok(());
+        }
+
+        let cbs = self.callbacks.clone();
+        let recv = self.base.recv.clone();
+        let monitor = self.base.monitor.clone();
+        let lt = self.base.thread.clone();
+        monitor.running.store(true, Ordering::SeqCst);
+        let handle = thread::Builder::new()
+            .name("mdl-signaler".to_string())
+            .spawn(move || {
+                event_loop(&recv, cbs);
+                monitor.running.store(false, Ordering::SeqCst);
+
+                let (ref state, ref exited) = *lt;
+                let mut t = lock(state);
+                if t.id == Some(thread::current().id()) {
+                    t.stopping = false;
+                    t.id = None;
+                    t.handle = None;
+                }
+                exited.notify_all();
+            })?;
+        t.id = Some(handle.thread().id());
+        t.handle = Some(handle);
+
+        Ok(())
+    }
+
+    /// Stops the loop thread of this signaler and all its clones. The
+    /// signals emitted before this call and the pending coalesced signals
+    /// are dispatched and then the loop thread is joined. Subscriptions are
+    /// kept, so the signaler can be started again.
+    ///
+    /// If this is called from a callback, the loop stops after the callback
+    /// returns, without waiting for it. The signaler is stopping until then,
+    /// and `start` waits for the loop thread to exit
+    pub fn stop(&self) {
+        let handle = {
+            let mut t = lock(&self.base.thread.0);
+            if t.handle.is_some() && !t.stopping {
+                let _ = self.base.main.send(Msg::Stop);
+                t.stopping = true;
+            }
+            match t.id == Some(thread::current().id()) {
+                // the loop thread can't join itself
+                true => None,
+                false => t.handle.take(),
+            }
+        };
+        // without the lock, the callbacks can call start or stop
+        match handle {
+            Some(handle) => { let _ = handle.join(); }
+            None => self.wait_stopped(),
+        }
+
+        // waiting for the pooled callbacks, except from a worker thread
+        let pool = lock(&self.callbacks).pool.clone();
+        if let Some(pool) = pool {
+            if !IN_WORKER.with(|w| w.get()) {
+                pool.flush();
+            }
+        }
+    }
+
+    /// Waits until the loop thread exits if the signaler is stopping,
+    /// except from the loop thread
+    fn wait_stopped(&self) {
+        let (ref state, ref exited) = *self.base.thread;
+        let t = lock(state);
+        if t.id != Some(thread::current().id()) && !IN_WORKER.with(|w| w.get()) {
+            let _t = exited.wait_while(t, |t| t.stopping);
+        }
     }
 
     /// subscribe a callback to a signal
-    /// This callback will be called with all signals that starts with the
-    /// `signal` string, for example, if you subscribe a callback to the signal
-    /// "custom-signal", this callback will have the following behaviour:
+    /// This callback will be called with all signals that matches the
+    /// `signal` pattern. A string is a prefix pattern, for example, if you
+    /// subscribe a callback to the signal "custom-signal", this callback will
+    /// have the following behaviour:
     ///
     ///   signal                         | f is called
     ///   -------------------------------+-------------
@@ -145,48 +547,150 @@ impl SignalerAsync {
     ///   "custom-signa"                 | false
     ///   "other signal"                 | false
     ///
-    /// This method returns the callback id that can be used to unsubscribe
-    pub fn subscribe(&self, signal: &str, f: Box<dyn Fn(Signal) + Send + 'static>)
-        -> Result<u32, Error> {
+    /// Use `Pattern::exact`, `Pattern::glob` or `Pattern::regex` to subscribe
+    /// with other kind of patterns.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe<P: Into<Pattern>>(&self, signal: P,
+                                       f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBack, signal, Handler::One(f), None, false)
+    }
+
+    /// subscribe a callback to a signal collapsing repeated signals. When a
+    /// signal comes, it's delivered after the `window` time, and all the
+    /// signals with the same name that comes in that time replaces the
+    /// pending one, so the callback is called only once with the last one.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe_coalesced<P: Into<Pattern>>(&self, signal: P, window: Duration,
+                                                 f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBack, signal, Handler::One(f), Some(Coalesce::Key(window)), false)
+    }
+
+    /// subscribe a callback to a signal receiving the signals in batches.
+    /// When a signal comes, all the signals that comes in the next `window`
+    /// time are delivered together, in the order they were emitted, in one
+    /// callback call.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe_batch<P: Into<Pattern>>(&self, signal: P, window: Duration,
+                                             f: Box<dyn Fn(Vec<Signal>) + Send + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBack, signal, Handler::Batch(f), Some(Coalesce::Batch(window)), false)
+    }
+
+    /// subscribe a callback that is called only once, with the first signal
+    /// that matches. After that, the callback is unsubscribed
+    pub fn subscribe_once<P: Into<Pattern>>(&self, signal: P,
+                                            f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBack, signal, Handler::One(f), None, true)
+    }
+
+    /// subscribe a callback that is called in the worker pool, see
+    /// `with_workers`. Signals with the same name are delivered to this
+    /// callback in order, but signals with different names can be delivered
+    /// at the same time in different threads, so the callback must be `Sync`
+    pub fn subscribe_pooled<P: Into<Pattern>>(&self, signal: P,
+                                              f: Box<dyn Fn(Signal) + Send + Sync + 'static>)
+        -> Result<Subscription, Error> {
+
+        let f: SharedFn = Arc::from(f);
+        let cb = f.clone();
+        subscribe!(self, CallBack, signal, Handler::One(Box::new(move |s| cb(s))), None, false, 0,
+                   Some(SharedCallBack(f)))
+    }
+
+    /// subscribe a callback with a priority. Callbacks with higher priority
+    /// are called first, and callbacks with the same priority are called in
+    /// subscription order. Other subscriptions have priority 0.
+    ///
+    /// The callback returns `Continue(false)` to stop the signal propagation,
+    /// so the next callbacks are not called with this signal
+    pub fn subscribe_priority<P: Into<Pattern>>(&self, signal: P, priority: i32,
+                                                f: Box<dyn Fn(Signal) -> Continue + Send + 'static>)
+        -> Result<Subscription, Error> {
 
-        subscribe!(self, CallBack, signal, f)
+        subscribe!(self, CallBack, signal, Handler::Chain(f), None, false, priority, None)
     }
 
-    /// Unsubscribe a callback by id. Use the id returned in the subscribe
-    /// method to remove this signal
+    /// Unsubscribe a callback by id. Use the id of a detached subscription to
+    /// remove the callback
     pub fn unsubscribe(&self, id: u32) {
-        unsubscribe!(self, CallBack, id);
+        unsubscribe!(self, id);
     }
 
+    /// Removes all callbacks subscribed with the `signal` prefix
     pub fn clear_signal(&self, signal: &str) {
-        let mut guard = self.callbacks.lock().unwrap();
-        guard.remove(signal);
+        let mut guard = lock(&self.callbacks);
+        guard.remove_prefix(signal);
+    }
+
+    /// Sets the function called when a callback fails. A panic in a callback
+    /// doesn't stop the signal dispatching, the panic is caught and reported
+    /// to this function. The function can replace itself with `on_error`
+    pub fn on_error(&self, f: Box<dyn Fn(&DeliveryError) + Send + Sync + 'static>) {
+        *lock(&self.base.monitor.on_error) = Some(Arc::from(f));
     }
 
+    /// Returns the signaler health information
+    pub fn status(&self) -> SignalerStatus {
+        let subscriptions = {
+            let mut guard = lock(&self.callbacks);
+            guard.purge();
+            guard.index.len()
+        };
+        self.base.monitor.status(subscriptions)
+    }
+
+    /// Starts the loop thread, see `start`
     pub fn signal_loop(&self) {
-        let cbs = self.callbacks.clone();
-        let recv = self.base.recv.clone();
-        thread::spawn(move || {
-            event_loop(&*recv.lock().unwrap(), cbs);
-        });
+        self.start().expect("can't start the signaler thread");
     }
 }
 
+impl Default for SignalerAsync {
+    fn default() -> SignalerAsync { SignalerAsync::new() }
+}
+
 impl SignalerSync {
+    #[allow(clippy::arc_with_non_send_sync)]
     pub fn new() -> SignalerSync {
-        let callbacks = Arc::new(Mutex::new(HashMap::new()));
         let base = SigBase::new();
+        base.monitor.running.store(true, Ordering::SeqCst);
+        let callbacks = Arc::new(Mutex::new(Callbacks::new(&base)));
         SignalerSync { base, callbacks }
     }
 
-    pub fn stop(&mut self) {
-        self.base.main = None;
+    /// Starts dispatching signals again after a `stop`. The signals emitted
+    /// while stopped are dispatched in the next calls
+    pub fn start(&self) {
+        self.base.monitor.running.store(true, Ordering::SeqCst);
+    }
+
+    /// Dispatches the pending signals and stops the signaler, the dispatch
+    /// methods do nothing until `start` is called. Subscriptions are kept
+    pub fn stop(&self) {
+        if self.is_running() {
+            self.drain();
+            stop_loop(&self.callbacks);
+        }
     }
 
     /// subscribe a callback to a signal
-    /// This callback will be called with all signals that starts with the
-    /// `signal` string, for example, if you subscribe a callback to the signal
-    /// "custom-signal", this callback will have the following behaviour:
+    /// This callback will be called with all signals that matches the
+    /// `signal` pattern. A string is a prefix pattern, for example, if you
+    /// subscribe a callback to the signal "custom-signal", this callback will
+    /// have the following behaviour:
     ///
     ///   signal                         | f is called
     ///   -------------------------------+-------------
@@ -197,52 +701,242 @@ impl SignalerSync {
     ///   "custom-signa"                 | false
     ///   "other signal"                 | false
     ///
-    /// This method returns the callback id that can be used to unsubscribe
-    pub fn subscribe(&self, signal: &str, f: Box<dyn Fn(Signal) + 'static>)
-        -> Result<u32, Error> {
+    /// Use `Pattern::exact`, `Pattern::glob` or `Pattern::regex` to subscribe
+    /// with other kind of patterns.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe<P: Into<Pattern>>(&self, signal: P,
+                                       f: Box<dyn Fn(Signal) + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBackSync, signal, HandlerSync::One(f), None, false)
+    }
+
+    /// subscribe a callback to a signal collapsing repeated signals. When a
+    /// signal comes, it's delivered after the `window` time, and all the
+    /// signals with the same name that comes in that time replaces the
+    /// pending one, so the callback is called only once with the last one.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe_coalesced<P: Into<Pattern>>(&self, signal: P, window: Duration,
+                                                 f: Box<dyn Fn(Signal) + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBackSync, signal, HandlerSync::One(f), Some(Coalesce::Key(window)), false)
+    }
+
+    /// subscribe a callback to a signal receiving the signals in batches.
+    /// When a signal comes, all the signals that comes in the next `window`
+    /// time are delivered together, in the order they were emitted, in one
+    /// callback call.
+    ///
+    /// This method returns the subscription guard, the callback is
+    /// unsubscribed when the guard drops
+    pub fn subscribe_batch<P: Into<Pattern>>(&self, signal: P, window: Duration,
+                                             f: Box<dyn Fn(Vec<Signal>) + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBackSync, signal, HandlerSync::Batch(f), Some(Coalesce::Batch(window)), false)
+    }
+
+    /// subscribe a callback that is called only once, with the first signal
+    /// that matches. After that, the callback is unsubscribed
+    pub fn subscribe_once<P: Into<Pattern>>(&self, signal: P,
+                                            f: Box<dyn Fn(Signal) + 'static>)
+        -> Result<Subscription, Error> {
 
-        subscribe!(self, CallBackSync, signal, f)
+        subscribe!(self, CallBackSync, signal, HandlerSync::One(f), None, true)
     }
 
-    /// Unsubscribe a callback by id. Use the id returned in the subscribe
-    /// method to remove this signal
+    /// subscribe a callback with a priority. Callbacks with higher priority
+    /// are called first, and callbacks with the same priority are called in
+    /// subscription order. Other subscriptions have priority 0.
+    ///
+    /// The callback returns `Continue(false)` to stop the signal propagation,
+    /// so the next callbacks are not called with this signal
+    pub fn subscribe_priority<P: Into<Pattern>>(&self, signal: P, priority: i32,
+                                                f: Box<dyn Fn(Signal) -> Continue + 'static>)
+        -> Result<Subscription, Error> {
+
+        subscribe!(self, CallBackSync, signal, HandlerSync::Chain(f), None, false, priority, None)
+    }
+
+    /// Unsubscribe a callback by id. Use the id of a detached subscription to
+    /// remove the callback
     pub fn unsubscribe(&self, id: u32) {
-        unsubscribe!(self, CallBackSync, id);
+        unsubscribe!(self, id);
     }
 
+    /// Removes all callbacks subscribed with the `signal` prefix
     pub fn clear_signal(&self, signal: &str) {
-        let mut guard = self.callbacks.lock().unwrap();
-        guard.remove(signal);
+        let mut guard = lock(&self.callbacks);
+        guard.remove_prefix(signal);
+    }
+
+    /// Sets the function called when a callback fails. A panic in a callback
+    /// doesn't stop the signal dispatching, the panic is caught and reported
+    /// to this function. The function can replace itself with `on_error`
+    pub fn on_error(&self, f: Box<dyn Fn(&DeliveryError) + Send + Sync + 'static>) {
+        *lock(&self.base.monitor.on_error) = Some(Arc::from(f));
+    }
+
+    /// Returns the signaler health information
+    pub fn status(&self) -> SignalerStatus {
+        let subscriptions = {
+            let mut guard = lock(&self.callbacks);
+            guard.purge();
+            guard.index.len()
+        };
+        self.base.monitor.status(subscriptions)
     }
 
+    /// Dispatches one pending signal if there's any, without blocking.
+    /// Returns false if the signaler is stopped
     pub fn signal_loop_sync(&self) -> bool {
-        let recv = self.base.recv.lock().unwrap();
-        match recv.try_recv() {
+        match self.recv_sync(Some(Instant::now())) {
             Ok(ref signal) => {
-                let mut cbs = self.callbacks.lock().unwrap();
-                signal_recv(signal, &mut *cbs);
+                signal_recv(signal, &self.callbacks);
                 true
             }
-            Err(TryRecvError::Empty) => {
-                true
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => false,
+        }
+    }
+
+    /// Blocks until a signal comes or the timeout is reached and dispatches
+    /// the signal. Returns the dispatched signal or `None` on timeout
+    pub fn wait_one(&self, timeout: Duration) -> Option<Signal> {
+        let deadline = Instant::now() + timeout;
+        match self.recv_sync(Some(deadline)) {
+            Ok(signal) => {
+                signal_recv(&signal, &self.callbacks);
+                Some(signal)
             }
-            Err(_) => {
-                let mut cbs = self.callbacks.lock().unwrap();
-                clear(&mut *cbs);
-                false
+            Err(_) => None,
+        }
+    }
+
+    /// Dispatches signals, blocking the current thread, until `predicate`
+    /// returns true for a dispatched signal. Returns false if the signaler is
+    /// stopped before that
+    pub fn run_until<F>(&self, mut predicate: F) -> bool
+        where F: FnMut(&Signal) -> bool {
+        loop {
+            match self.recv_sync(None) {
+                Ok(signal) => {
+                    signal_recv(&signal, &self.callbacks);
+                    if predicate(&signal) {
+                        return true;
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Dispatches all the pending signals without blocking. Returns the
+    /// number of dispatched signals
+    pub fn drain(&self) -> usize {
+        let mut n = 0;
+        while let Ok(signal) = self.recv_sync(Some(Instant::now())) {
+            signal_recv(&signal, &self.callbacks);
+            n += 1;
+        }
+        n
+    }
+
+    /// Returns a file descriptor that is readable while there are pending
+    /// signals, to integrate the signaler with external main loops like
+    /// glib, mio, epoll or calloop. When the fd is readable, call
+    /// `signal_loop_sync` or `drain` to dispatch the signals, the fd is not
+    /// readable again until there are new signals.
+    ///
+    /// The fd is owned by the signaler, it shouldn't be closed or read.
+    /// Coalesced signals are delivered when the window ends, so signalers
+    /// with coalesced subscriptions also need a timeout to dispatch them.
+    ///
+    /// This is only available in unix.
+    #[cfg(unix)]
+    pub fn fd(&self) -> Result<RawFd, Error> {
+        let mut notifier = lock(&self.base.notifier);
+        if notifier.is_none() {
+            let n = Notifier::new()?;
+            if self.base.monitor.pending() > 0 {
+                n.notify();
+            }
+            *notifier = Some(n);
+        }
+        Ok(notifier.as_ref().map(|n| n.fd()).unwrap_or(-1))
+    }
+
+    /// Waits for the next signal. Returns `Disconnected` if the signaler is
+    /// stopped
+    fn recv_sync(&self, deadline: Option<Instant>) -> Result<Signal, RecvTimeoutError> {
+        if !self.is_running() {
+            return Err(RecvTimeoutError::Disconnected);
+        }
+
+        let r = recv_until(&self.base.recv, &self.callbacks, deadline);
+        #[cfg(unix)]
+        if let Err(RecvTimeoutError::Timeout) = r {
+            self.clear_notifier();
+        }
+
+        match r? {
+            Msg::Signal(signal) => Ok(signal),
+            Msg::Stop => Err(RecvTimeoutError::Disconnected),
+        }
+    }
+
+    /// The queue is empty, the fd shouldn't be readable
+    #[cfg(unix)]
+    fn clear_notifier(&self) {
+        if let Some(ref n) = *lock(&self.base.notifier) {
+            n.clear();
+            // a signal emitted before clearing
+            if self.base.monitor.pending() > 0 {
+                n.notify();
             }
         }
     }
 }
 
+impl Default for SignalerSync {
+    fn default() -> SignalerSync { SignalerSync::new() }
+}
+
 // Trait implementation
 
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "callback {} failed: {}", self.id, self.message)
+    }
+}
+
+impl error::Error for DeliveryError {}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "monitor: {} delivered, {} failed",
+               self.delivered.load(Ordering::SeqCst),
+               self.failed.load(Ordering::SeqCst))
+    }
+}
+
 impl fmt::Debug for CallBack {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "callback: {}", self.id)
     }
 }
 
+impl fmt::Debug for SharedCallBack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "shared-callback")
+    }
+}
+
 impl fmt::Debug for CallBackSync {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "callback-sync: {}", self.id)
@@ -251,56 +945,564 @@ impl fmt::Debug for CallBackSync {
 
 impl CB for CallBack {
     fn id(&self) -> u32 { self.id }
-    fn call(&self, sig: Signal) { (*self.callback)(sig); }
+
+    fn call(&self, sig: Signal) -> Continue {
+        match self.callback {
+            Handler::One(ref f) => f(sig),
+            Handler::Batch(ref f) => f(vec![sig]),
+            Handler::Chain(ref f) => return f(sig),
+        };
+        Continue(true)
+    }
+
+    fn call_batch(&self, sigs: Vec<Signal>) {
+        match self.callback {
+            Handler::One(ref f) => sigs.into_iter().for_each(f),
+            Handler::Batch(ref f) => f(sigs),
+            Handler::Chain(ref f) => sigs.into_iter().for_each(|s| { f(s); }),
+        }
+    }
 }
 
 impl CB for CallBackSync {
     fn id(&self) -> u32 { self.id }
-    fn call(&self, sig: Signal) { (*self.callback)(sig); }
+
+    fn call(&self, sig: Signal) -> Continue {
+        match self.callback {
+            HandlerSync::One(ref f) => f(sig),
+            HandlerSync::Batch(ref f) => f(vec![sig]),
+            HandlerSync::Chain(ref f) => return f(sig),
+        };
+        Continue(true)
+    }
+
+    fn call_batch(&self, sigs: Vec<Signal>) {
+        match self.callback {
+            HandlerSync::One(ref f) => sigs.into_iter().for_each(f),
+            HandlerSync::Batch(ref f) => f(sigs),
+            HandlerSync::Chain(ref f) => sigs.into_iter().for_each(|s| { f(s); }),
+        }
+    }
 }
 
 impl Signaler for SignalerAsync {
-    fn base<'a>(&'a self) -> &'a SigBase { &self.base }
+    fn base(&self) -> &SigBase { &self.base }
+
+    fn connect(&self, pattern: Pattern, f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+        self.subscribe(pattern, f)
+    }
 }
 
 impl Signaler for SignalerSync {
-    fn base<'a>(&'a self) -> &'a SigBase { &self.base }
+    fn base(&self) -> &SigBase { &self.base }
+
+    fn connect(&self, pattern: Pattern, f: Box<dyn Fn(Signal) + Send + 'static>)
+        -> Result<Subscription, Error> {
+        self.subscribe(pattern, f)
+    }
+
+    /// The `SignalerSync` waiter dispatches the signals in the waiting
+    /// thread
+    fn waiter<P: Into<Pattern>>(&self, pattern: P) -> Result<Waiter<'_>, Error> {
+        let (tx, rx) = channel();
+        let sub = self.connect(pattern.into(), Box::new(move |sig| {
+            let _ = tx.send(sig);
+        }))?;
+
+        Ok(Waiter {
+            rx,
+            _sub: sub,
+            dispatch: Some(Box::new(move |timeout| {
+                self.wait_one(timeout);
+                self.is_running()
+            })),
+        })
+    }
+}
+
+impl<'a> Waiter<'a> {
+    /// Blocks until the next signal that matches the pattern or the timeout.
+    /// Returns `None` on timeout
+    pub fn wait(&self, timeout: Duration) -> Option<Signal> {
+        let dispatch = match self.dispatch {
+            Some(ref d) => d,
+            None => return self.rx.recv_timeout(timeout).ok(),
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(sig) = self.rx.try_recv() {
+                return Some(sig);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            if !dispatch(deadline - now) {
+                return self.rx.try_recv().ok();
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Waiter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "waiter")
+    }
+}
+
+impl Delayed {
+    fn new(mode: Coalesce) -> Delayed {
+        Delayed { mode, seq: 0, pending: BTreeMap::new(), names: HashMap::new() }
+    }
+
+    fn push(&mut self, sig: Signal, now: Instant) {
+        match self.mode {
+            Coalesce::Key(window) => {
+                if let Some(seq) = self.names.get(&sig.name) {
+                    // same name in the window, replacing with the last one
+                    if let Some(p) = self.pending.get_mut(seq) {
+                        p.1 = sig;
+                    }
+                    return;
+                }
+                self.names.insert(sig.name.clone(), self.seq);
+                self.pending.insert(self.seq, (now + window, sig));
+            }
+            Coalesce::Batch(window) => {
+                let deadline = self.next().unwrap_or(now + window);
+                self.pending.insert(self.seq, (deadline, sig));
+            }
+        }
+        self.seq += 1;
+    }
+
+    /// Takes the signals which window has ended, or all if `force`
+    fn due(&mut self, now: Instant, force: bool) -> Vec<Signal> {
+        let mut out = vec![];
+        while let Some((&seq, &(deadline, _))) = self.pending.iter().next() {
+            if !force && deadline > now {
+                break;
+            }
+            if let Some((_, sig)) = self.pending.remove(&seq) {
+                if let Coalesce::Key(_) = self.mode {
+                    self.names.remove(&sig.name);
+                }
+                out.push(sig);
+            }
+        }
+        out
+    }
+
+    fn next(&self) -> Option<Instant> {
+        self.pending.values().next().map(|(deadline, _)| *deadline)
+    }
+}
+
+impl Subscription {
+    fn new(id: u32, alive: Arc<AtomicBool>, dropped: &Arc<Mutex<Vec<u32>>>) -> Subscription {
+        Subscription { id, alive, dropped: Arc::downgrade(dropped), detached: false }
+    }
+
+    /// The subscription id, that can be used to unsubscribe
+    pub fn id(&self) -> u32 { self.id }
+
+    /// Keeps the callback subscribed after this guard drops. Returns the
+    /// subscription id that can be used to unsubscribe
+    pub fn detach(mut self) -> u32 {
+        self.detached = true;
+        self.id
+    }
+
+    /// Unsubscribes the callback, this is the same as dropping the guard
+    pub fn unsubscribe(self) {}
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if self.detached {
+            return;
+        }
+
+        self.alive.store(false, Ordering::SeqCst);
+        if let Some(dropped) = self.dropped.upgrade() {
+            lock(&dropped).push(self.id);
+        }
+    }
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "subscription: {}", self.id)
+    }
+}
+
+impl<T: CB> Slot<T> {
+    fn new(cb: T, coalesce: Option<Coalesce>, once: bool, priority: i32) -> Slot<T> {
+        Slot {
+            id: cb.id(),
+            cb: Mutex::new(cb),
+            delayed: coalesce.map(|c| Mutex::new(Delayed::new(c))),
+            alive: Arc::new(AtomicBool::new(true)),
+            once,
+            priority,
+            shared: None,
+        }
+    }
+
+    /// Calls the callback or waits for the coalescing window. Returns false
+    /// if the callback stops the signal propagation
+    fn deliver(&self, sig: &Signal, monitor: &Monitor) -> bool {
+        // the once callbacks are marked as not alive before calling them
+        let alive = match self.once {
+            true => self.alive.swap(false, Ordering::SeqCst),
+            false => self.alive.load(Ordering::SeqCst),
+        };
+        if !alive {
+            return true;
+        }
+
+        match self.delayed {
+            Some(ref d) => {
+                lock(d).push(sig.clone(), Instant::now());
+                true
+            }
+            None => {
+                let r = match self.shared {
+                    Some(SharedCallBack(ref f)) => catch_unwind(AssertUnwindSafe(|| {
+                        f(sig.clone());
+                        Continue(true)
+                    })),
+                    None => {
+                        let cb = lock(&self.cb);
+                        catch_unwind(AssertUnwindSafe(|| cb.call(sig.clone())))
+                    }
+                };
+                // a failed callback doesn't stop the propagation
+                let next = r.as_ref().map(|c| c.0).unwrap_or(true);
+                monitor.report(self.id, r.map(|_| ()), || vec![sig.clone()]);
+                next
+            }
+        }
+    }
+
+    /// Delivers the delayed signals which window has ended, or all if
+    /// `force`. Returns the next deadline
+    fn flush(&self, now: Instant, force: bool, monitor: &Monitor) -> Option<Instant> {
+        let (mode, due, next) = {
+            let mut d = lock(self.delayed.as_ref()?);
+            (d.mode, d.due(now, force), d.next())
+        };
+
+        if due.is_empty() {
+            return next;
+        }
+
+        let cb = lock(&self.cb);
+        match mode {
+            Coalesce::Batch(_) => {
+                let sigs = due.clone();
+                let r = catch_unwind(AssertUnwindSafe(|| cb.call_batch(sigs)));
+                monitor.report(self.id, r, || due);
+            }
+            Coalesce::Key(_) => {
+                for sig in due {
+                    let r = catch_unwind(AssertUnwindSafe(|| cb.call(sig.clone())));
+                    monitor.report(self.id, r.map(|_| ()), || vec![sig]);
+                }
+            }
+        }
+        next
+    }
+}
+
+impl Monitor {
+    /// Counts the callback call result and calls the error hook on failure
+    fn report<F>(&self, id: u32, r: thread::Result<()>, signals: F)
+        where F: FnOnce() -> Vec<Signal> {
+        let panic = match r {
+            Ok(_) => {
+                self.delivered.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            Err(panic) => panic,
+        };
+
+        self.failed.fetch_add(1, Ordering::SeqCst);
+        let message = match panic.downcast_ref::<&str>() {
+            Some(m) => m.to_string(),
+            None => panic.downcast_ref::<String>().cloned()
+                .unwrap_or_else(|| "callback panicked".to_string()),
+        };
+        let err = DeliveryError { id, signals: signals(), message };
+
+        // called without the lock, the hook can replace itself or fail again
+        let hook = lock(&self.on_error).clone();
+        if let Some(hook) = hook {
+            // the error hook can fail too, but nothing more can be done
+            let _ = catch_unwind(AssertUnwindSafe(|| hook(&err)));
+        }
+        *lock(&self.last_error) = Some(err);
+    }
+
+    /// Signals emitted and not received yet
+    fn pending(&self) -> u64 {
+        let emitted = self.emitted.load(Ordering::SeqCst);
+        let received = self.received.load(Ordering::SeqCst);
+        emitted.saturating_sub(received)
+    }
+
+    fn status(&self, subscriptions: usize) -> SignalerStatus {
+        SignalerStatus {
+            running: self.running.load(Ordering::SeqCst),
+            subscriptions,
+            pending: self.pending(),
+            delivered: self.delivered.load(Ordering::SeqCst),
+            failed: self.failed.load(Ordering::SeqCst),
+            last_error: lock(&self.last_error).clone(),
+        }
+    }
+}
+
+impl<T> Callbacks<T> {
+    fn new(base: &SigBase) -> Callbacks<T> {
+        Callbacks {
+            trie: Dispatcher::new(),
+            index: HashMap::new(),
+            delayed: BTreeMap::new(),
+            dropped: base.dropped.clone(),
+            monitor: base.monitor.clone(),
+            pool: None,
+        }
+    }
+
+    fn insert(&mut self, pattern: Pattern, slot: Arc<Slot<T>>) {
+        self.purge();
+
+        if slot.delayed.is_some() {
+            self.delayed.insert(slot.id, slot.clone());
+        }
+        self.index.insert(slot.id, pattern.anchor().to_string());
+        self.trie.insert(slot.id, pattern, slot);
+    }
+
+    fn remove(&mut self, id: u32) {
+        self.purge();
+        self.remove_id(id);
+    }
+
+    fn remove_id(&mut self, id: u32) {
+        if let Some(anchor) = self.index.remove(&id) {
+            self.trie.remove(&anchor, id);
+        }
+        self.delayed.remove(&id);
+    }
+
+    fn remove_prefix(&mut self, prefix: &str) {
+        self.purge();
+
+        for slot in self.trie.remove_prefix(prefix) {
+            self.index.remove(&slot.id);
+            self.delayed.remove(&slot.id);
+        }
+    }
+
+    /// Removes the dropped subscriptions
+    fn purge(&mut self) {
+        let dropped = std::mem::take(&mut *lock(&self.dropped));
+        for id in dropped {
+            self.remove_id(id);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.trie.clear();
+        self.index.clear();
+        self.delayed.clear();
+    }
+}
+
+#[cfg(feature = "async")]
+impl SignalStream {
+    /// Waits for the next signal. Returns `None` if the signaler stops
+    pub async fn recv(&mut self) -> Option<Signal> {
+        use futures_core::Stream;
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for SignalStream {
+    type Item = Signal;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Signal>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl fmt::Debug for SignalStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "signal-stream")
+    }
 }
 
 // static functions
 
-fn event_loop<T: CB>(receiver: &Receiver<Signal>,
-                     cbs: Arc<Mutex< HashMap<String, Vec<T>> >>) {
+fn event_loop<T: CB>(receiver: &Mutex<Receiver<Msg>>,
+                     cbs: Arc<Mutex< Callbacks<T> >>) {
     loop {
-        match receiver.recv() {
-            Ok(ref signal) => {
-                let mut cbs = cbs.lock().unwrap();
-                signal_recv(signal, &mut *cbs);
+        match recv_until(receiver, &cbs, None) {
+            Ok(Msg::Signal(ref signal)) => {
+                signal_recv(signal, &cbs);
+            }
+            Ok(Msg::Stop) => {
+                stop_loop(&cbs);
+                break;
             }
             Err(_) => {
-                let mut cbs = cbs.lock().unwrap();
-                clear(&mut *cbs);
+                // all the signalers dropped
+                stop_loop(&cbs);
+                lock(&cbs).clear();
                 break;
             }
         };
     }
 }
 
-fn signal_recv<T: CB>(signal: &Signal, cbs: &mut HashMap<String, Vec<T>>) {
-    for (ref k, ref v) in cbs.iter() {
-        if !&signal.name[..].starts_with(&k[..]) {
+/// Waits for the next signal until the deadline, or forever if there's no
+/// deadline. Delayed signals are delivered while waiting, when the
+/// coalescing window ends
+fn recv_until<T: CB>(receiver: &Mutex<Receiver<Msg>>,
+                     cbs: &Mutex< Callbacks<T> >,
+                     deadline: Option<Instant>) -> Result<Msg, RecvTimeoutError> {
+    loop {
+        let now = Instant::now();
+        let next = flush_delayed(cbs, now, false);
+        let until = match (deadline, next) {
+            (Some(d), Some(n)) => Some(d.min(n)),
+            (d, n) => d.or(n),
+        };
+
+        let r = {
+            let rx = lock(receiver);
+            match until {
+                Some(u) => rx.recv_timeout(u.saturating_duration_since(now)),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            }
+        };
+
+        match r {
+            Ok(Msg::Signal(signal)) => {
+                lock(cbs).monitor.received.fetch_add(1, Ordering::SeqCst);
+                return Ok(Msg::Signal(signal));
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+                    return Err(RecvTimeoutError::Timeout);
+                }
+            }
+            r => return r,
+        }
+    }
+}
+
+/// Delivers the delayed signals which window has ended. Returns the next
+/// deadline
+fn flush_delayed<T: CB>(cbs: &Mutex< Callbacks<T> >, now: Instant, force: bool)
+    -> Option<Instant> {
+    let (delayed, monitor): (Vec<Arc<Slot<T>>>, _) = {
+        let guard = lock(cbs);
+        (guard.delayed.values().cloned().collect(), guard.monitor.clone())
+    };
+    delayed.iter()
+        .filter_map(|slot| slot.flush(now, force, &monitor))
+        .min()
+}
+
+/// The signaler is stopped, delivers all pending coalesced signals
+fn stop_loop<T: CB>(cbs: &Mutex< Callbacks<T> >) {
+    flush_delayed(cbs, Instant::now(), true);
+    lock(cbs).monitor.running.store(false, Ordering::SeqCst);
+}
+
+/// Calls all the callbacks that match the signal, by priority and then in
+/// subscription order, until one of them stops the propagation. The
+/// matching callbacks are collected before calling them, so the callbacks
+/// lock is not held during the call and a callback can subscribe or
+/// unsubscribe
+fn signal_recv<T: CB>(signal: &Signal, cbs: &Mutex< Callbacks<T> >) {
+    let (mut matched, monitor, dropped, pool): (Vec<Arc<Slot<T>>>, _, _, _) = {
+        let mut guard = lock(cbs);
+        guard.purge();
+        let matched = guard.trie.matches(&signal.name).into_iter().cloned().collect();
+        (matched, guard.monitor.clone(), guard.dropped.clone(), guard.pool.clone())
+    };
+    matched.sort_by_key(|c| (Reverse(c.priority), c.id));
+
+    for c in matched.iter() {
+        if let (Some(_), Some(pool)) = (&c.shared, &pool) {
+            pool.send(c.clone(), signal.clone());
             continue;
         }
 
-        for c in v.iter() {
-            c.call(signal.clone());
+        let next = c.deliver(signal, &monitor);
+        if c.once && !c.alive.load(Ordering::SeqCst) {
+            lock(&dropped).push(c.id);
+        }
+        if !next {
+            break;
         }
     }
 }
 
-fn clear<T: CB>(cbs: &mut HashMap<String, Vec<T>>) {
-    for (_, v) in cbs.iter_mut() {
-        v.clear();
+impl<T> Pool<T> {
+    /// Sends the signal to the worker of the signal name
+    fn send(&self, slot: Arc<Slot<T>>, signal: Signal) {
+        let mut hasher = DefaultHasher::new();
+        signal.name.hash(&mut hasher);
+        let i = (hasher.finish() % self.workers.len() as u64) as usize;
+        let _ = self.workers[i].send(Job::Call(slot, signal));
+    }
+
+    /// Waits until all the workers are idle
+    fn flush(&self) {
+        let (tx, rx) = channel();
+        let n = self.workers.iter()
+            .filter(|w| w.send(Job::Flush(tx.clone())).is_ok())
+            .count();
+        for _ in 0..n {
+            let _ = rx.recv();
+        }
     }
 }
 
+impl Pool<CallBack> {
+    fn new(size: usize, monitor: Arc<Monitor>) -> Result<Pool<CallBack>, Error> {
+        let mut workers = vec![];
+        for i in 0..size {
+            let (tx, rx) = channel::<Job<CallBack>>();
+            let monitor = monitor.clone();
+            thread::Builder::new()
+                .name(format!("mdl-worker-{}", i))
+                .spawn(move || {
+                    IN_WORKER.with(|w| w.set(true));
+                    for job in rx {
+                        match job {
+                            Job::Call(slot, signal) => { slot.deliver(&signal, &monitor); }
+                            Job::Flush(done) => { let _ = done.send(()); }
+                        }
+                    }
+                })?;
+            workers.push(tx);
+        }
+        Ok(Pool { workers })
+    }
+}
+
+/// Locks the mutex ignoring the poisoning. Callbacks are called catching
+/// panics, so the protected data is always consistent
+fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
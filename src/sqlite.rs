@@ -0,0 +1,169 @@
+use anyhow::Error;
+use anyhow::anyhow;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use std::collections::HashSet;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::store::Store;
+use crate::store::Continue;
+
+/// SQLite cache. This struct implements the Store trait so it can be used
+/// to cache Model structs, in filesystems where LMDB can't be used, like
+/// network filesystems
+///
+/// Each db is a table with the key as primary key, so the prefix iteration
+/// is a range query over the key index.
+pub struct SqliteCache {
+    /// database file path in the filesystem
+    pub path: String,
+    conn: Mutex<Conn>,
+}
+
+struct Conn {
+    conn: Connection,
+    /// tables already created
+    tables: HashSet<&'static str>,
+}
+
+impl SqliteCache {
+    pub fn new(path: &str) -> Result<SqliteCache, Error> {
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                let _ = create_dir_all(parent);
+            }
+        }
+
+        let conn = Connection::open(path)?;
+        // other processes or threads can be writing
+        conn.busy_timeout(Duration::from_secs(5))?;
+
+        Ok(SqliteCache {
+            path: path.to_string(),
+            conn: Mutex::new(Conn { conn, tables: HashSet::new() }),
+        })
+    }
+
+    /// Locks the connection and creates the `db` table if needed
+    fn table(&self, db: &'static str) -> Result<MutexGuard<'_, Conn>, Error> {
+        let mut guard = self.conn.lock().map_err(|_| anyhow!("DB ERROR"))?;
+        if !guard.tables.contains(db) {
+            let sql = format!("CREATE TABLE IF NOT EXISTS {} (
+                                   key TEXT PRIMARY KEY NOT NULL,
+                                   value BLOB NOT NULL
+                               ) WITHOUT ROWID", table(db));
+            guard.conn.execute(&sql, [])
+                .map_err(|e| anyhow!("error opening the db {}, {}", db, e))?;
+            guard.tables.insert(db);
+        }
+        Ok(guard)
+    }
+
+    /// Calls `f` with the keys with the prefix that are `op` (`>` or `>=`)
+    /// than `start`, the index is used to find the first key
+    fn iter_range<F>(&self, db: &'static str, prefix: &str, op: &str, start: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let guard = self.table(db)?;
+        let end = prefix_end(prefix);
+        let sql = match end {
+            Some(_) => format!("SELECT key, value FROM {} WHERE key {} ?1 AND key < ?2 ORDER BY key", table(db), op),
+            None => format!("SELECT key, value FROM {} WHERE key {} ?1 ORDER BY key", table(db), op),
+        };
+
+        let mut stmt = guard.conn.prepare_cached(&sql)?;
+        let mut rows = match end {
+            Some(ref end) => stmt.query(params![start, end])?,
+            None => stmt.query(params![start])?,
+        };
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let value = row.get_ref(1)?.as_blob()?;
+            if let Continue(false) = f(&key, value) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Store for SqliteCache {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        let guard = self.table(db)?;
+        let sql = format!("INSERT OR REPLACE INTO {} (key, value) VALUES (?1, ?2)", table(db));
+        guard.conn.prepare_cached(&sql)?.execute(params![key, value])?;
+        Ok(())
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        let guard = self.table(db)?;
+        let sql = format!("SELECT value FROM {} WHERE key = ?1", table(db));
+        let data: Option<Vec<u8>> = guard.conn.prepare_cached(&sql)?
+            .query_row(params![key], |row| row.get(0))
+            .optional()?;
+        let data = data.ok_or_else(|| anyhow!("Not found, pull {}:{}", db, key))?;
+        formatter(&data)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.iter_range(db, prefix, ">=", prefix, f)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        match after >= prefix {
+            true => self.iter_range(db, prefix, ">", after, f),
+            false => self.iter_range(db, prefix, ">=", prefix, f),
+        }
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        let guard = self.table(db)?;
+        let sql = format!("DELETE FROM {} WHERE key = ?1", table(db));
+        let deleted = guard.conn.prepare_cached(&sql)?.execute(params![key])?;
+        match deleted {
+            0 => Err(anyhow!("Not found, rm {}:{}", db, key)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Quoted table name of the db
+fn table(db: &str) -> String {
+    format!("\"{}\"", db.replace('"', "\"\""))
+}
+
+/// First string after all the strings that starts with the prefix, `None`
+/// if there's no such string. The keys are compared as UTF-8 bytes, in the
+/// same order as the unicode code points
+fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        let next = match c as u32 {
+            0xD7FF => Some('\u{E000}'),
+            n => std::char::from_u32(n + 1),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
@@ -1,5 +1,6 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use anyhow::Error;
 use anyhow::anyhow;
@@ -25,6 +26,28 @@ pub trait Store {
         -> Result<(), Error>
         where F: Fn(&[u8]) -> Continue;
 
+    /// Iterates like `iter`, passing also the key of each object to f. The
+    /// keys are in order. Stores that can't list the keys return an error
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        let _ = f;
+        Err(anyhow!("iter_kv is not supported by this store, {}:{}", db, prefix))
+    }
+
+    /// Iterates like `iter_kv` over the keys with the prefix that go after
+    /// the key `after`, to continue a previous iteration. Stores that can
+    /// seek to a key override this, by default the previous keys are
+    /// skipped
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        self.iter_kv(db, prefix, |k, v| match k > after {
+            true => f(k, v),
+            false => Continue(true),
+        })
+    }
+
     /// Retrieves all items in the database that starts with the prefix key
     fn all<F, T>(&self, db: &'static str, prefix: &str, formatter: F)
         -> Result<Vec<T>, Error>
@@ -49,3 +72,46 @@ pub trait Store {
     fn rm(&self, db: &'static str, key: &str) -> Result<(), Error>;
 }
 
+
+/// A shared store is also a store, so an `Arc<Cache>` can be shared between
+/// threads and used as a normal `Store`
+impl<S: Store> Store for Arc<S> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        (**self).push(db, key, value)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        (**self).pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        (**self).iter(db, prefix, f)
+    }
+
+    fn iter_kv<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        (**self).iter_kv(db, prefix, f)
+    }
+
+    fn iter_kv_after<F>(&self, db: &'static str, prefix: &str, after: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&str, &[u8]) -> Continue {
+        (**self).iter_kv_after(db, prefix, after, f)
+    }
+
+    fn all<F, T>(&self, db: &'static str, prefix: &str, formatter: F)
+        -> Result<Vec<T>, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        (**self).all(db, prefix, formatter)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        (**self).rm(db, key)
+    }
+}
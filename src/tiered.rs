@@ -0,0 +1,251 @@
+use anyhow::Error;
+use anyhow::anyhow;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Included, Unbounded};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::cache::is_not_found;
+use crate::store::Store;
+use crate::store::Continue;
+
+/// How the writes reach the back store
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteMode {
+    /// each write goes to both stores
+    Through,
+    /// writes go to the front store and are written to the back store with
+    /// `flush`, or when there are `max_pending` writes
+    Behind { max_pending: usize },
+}
+
+/// Pending writes of the write-behind mode, `None` values are deletions
+type Pending = BTreeMap<(&'static str, String), Option<Vec<u8>>>;
+
+/// Two level store, a fast `front` store, like the in memory `BCache`, in
+/// front of a persistent `back` store, like the LMDB `Cache`.
+///
+/// Reads are served from the front store and fall back to the back store,
+/// copying the value to the front. The back store has all the objects, so
+/// the front store can be a cache with limits. `iter` reads from the back
+/// store, merged with the pending writes in write-behind mode, so the back
+/// store must support `iter_kv`.
+///
+/// In write-behind mode the pending writes are lost if they aren't flushed.
+/// This struct implements clone, all the clones share the pending writes.
+///
+/// ```ignore
+/// let store = TieredStore::new(BCache::new().unwrap(), Cache::new(db).unwrap(),
+///                              WriteMode::Behind { max_pending: 100 });
+/// a.store(&store).unwrap();
+/// store.flush().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct TieredStore<F, B> {
+    front: F,
+    back: B,
+    mode: WriteMode,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl<F: Store, B: Store> TieredStore<F, B> {
+    pub fn new(front: F, back: B, mode: WriteMode) -> TieredStore<F, B> {
+        TieredStore { front, back, mode, pending: Arc::new(Mutex::new(BTreeMap::new())) }
+    }
+
+    pub fn front(&self) -> &F {
+        &self.front
+    }
+
+    pub fn back(&self) -> &B {
+        &self.back
+    }
+
+    /// Number of writes not written to the back store yet
+    pub fn pending(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Writes the pending writes to the back store. Each write stays
+    /// pending until it's in the back store, so the writes that fail stay
+    /// pending and the reads find them meanwhile
+    pub fn flush(&self) -> Result<(), Error> {
+        let writes: Vec<_> = self.lock().iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for (k, value) in writes {
+            let (db, ref key) = k;
+            match value {
+                Some(ref v) => self.back.push(db, key, v.clone())?,
+                None => self.back_rm(db, key)?,
+            }
+            // unless it's written again while flushing
+            let mut pending = self.lock();
+            if pending.get(&k) == Some(&value) {
+                pending.remove(&k);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the key from the front store, so the next read comes from the
+    /// back store. Use this when the back store is modified directly
+    pub fn invalidate(&self, db: &'static str, key: &str) {
+        // the key can be not cached
+        let _ = self.front.rm(db, key);
+    }
+
+    /// Removes the key from the back store, the key can be only in the
+    /// front store
+    fn back_rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        match self.back.rm(db, key) {
+            Err(_) if self.back.pull(db, key, |_| Ok(())).is_err() => Ok(()),
+            r => r,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The pending value of the key, `Some(None)` if it's deleted
+    fn pending_value(&self, db: &'static str, key: &str) -> Option<Option<Vec<u8>>> {
+        self.lock().get(&(db, key.to_string())).cloned()
+    }
+
+    fn write(&self, db: &'static str, key: &str, value: Option<Vec<u8>>) -> Result<(), Error> {
+        let n = {
+            let mut pending = self.lock();
+            pending.insert((db, key.to_string()), value);
+            pending.len()
+        };
+        match self.mode {
+            WriteMode::Behind { max_pending } if n >= max_pending => self.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<F: Store, B: Store> Store for TieredStore<F, B> {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        match self.mode {
+            WriteMode::Through => self.back.push(db, key, value.clone())?,
+            WriteMode::Behind { .. } => self.write(db, key, Some(value.clone()))?,
+        }
+        // the value is stored anyway, if it can't be cached the old value
+        // can't stay in the front store
+        if self.front.push(db, key, value).is_err() {
+            self.invalidate(db, key);
+        }
+        Ok(())
+    }
+
+    fn pull<FF, T>(&self, db: &'static str, key: &str, formatter: FF)
+        -> Result<T, Error>
+        where FF: Fn(&[u8]) -> Result<T, Error> {
+        // the front store can evict pending writes
+        match self.pending_value(db, key) {
+            Some(Some(value)) => return formatter(&value),
+            Some(None) => return Err(anyhow!("Not found, pull {}:{}", db, key)),
+            None => {}
+        }
+
+        if let Ok(v) = self.front.pull(db, key, &formatter) {
+            return Ok(v);
+        }
+
+        let data = self.back.pull(db, key, |data| Ok(data.to_vec()))?;
+        // the value is read even if it can't be cached, like with a full or
+        // read-only front store
+        let _ = self.front.push(db, key, data.clone());
+        formatter(&data)
+    }
+
+    fn iter<FF>(&self, db: &'static str, prefix: &str, f: FF)
+        -> Result<(), Error>
+        where FF: Fn(&[u8]) -> Continue {
+        self.iter_kv(db, prefix, |_, v| f(v))
+    }
+
+    fn iter_kv<FF>(&self, db: &'static str, prefix: &str, f: FF)
+        -> Result<(), Error>
+        where FF: Fn(&str, &[u8]) -> Continue {
+        let start = (db, prefix.to_string());
+        let pending: Vec<(String, Option<Vec<u8>>)> = self.lock()
+            .range((Included(&start), Unbounded))
+            .take_while(|((d, k), _)| *d == db && k.starts_with(prefix))
+            .map(|((_, k), v)| (k.clone(), v.clone()))
+            .collect();
+
+        if pending.is_empty() {
+            return self.back.iter_kv(db, prefix, f);
+        }
+
+        // merges the back store keys with the pending writes, in order
+        let pending = RefCell::new(pending.into_iter().peekable());
+        let stopped = RefCell::new(false);
+        let seen = RefCell::new(false);
+        let emit = |k: &str, v: &[u8]| {
+            let c = f(k, v);
+            *stopped.borrow_mut() = !c.0;
+            c
+        };
+        let r = self.back.iter_kv(db, prefix, |k, v| {
+            *seen.borrow_mut() = true;
+            let mut pending = pending.borrow_mut();
+            while let Some((pk, pv)) = pending.next_if(|(pk, _)| pk.as_str() <= k) {
+                let overwritten = pk == k;
+                if let Some(pv) = pv {
+                    if let Continue(false) = emit(&pk, &pv) {
+                        return Continue(false);
+                    }
+                }
+                if overwritten {
+                    return Continue(true);
+                }
+            }
+            emit(k, v)
+        });
+        if *stopped.borrow() {
+            return Ok(());
+        }
+
+        let rest: Vec<_> = pending.into_inner().collect();
+        if let Err(err) = r {
+            // LMDB returns an error if there's no key with the prefix, the
+            // pending writes can still have some. Other errors are real
+            if *seen.borrow() || !is_not_found(&err) || rest.iter().all(|(_, v)| v.is_none()) {
+                return Err(err);
+            }
+        }
+        for (k, v) in rest {
+            if let Some(v) = v {
+                if let Continue(false) = f(&k, &v) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        let r = match self.mode {
+            WriteMode::Through => self.back.rm(db, key),
+            WriteMode::Behind { .. } => {
+                let exists = match self.pending_value(db, key) {
+                    Some(value) => value.is_some(),
+                    None => self.front.pull(db, key, |_| Ok(())).is_ok() ||
+                            self.back.pull(db, key, |_| Ok(())).is_ok(),
+                };
+                match exists {
+                    true => self.write(db, key, None),
+                    false => Err(anyhow!("Not found, rm {}:{}", db, key)),
+                }
+            }
+        };
+        self.invalidate(db, key);
+        r
+    }
+}
@@ -0,0 +1,231 @@
+//! Time to live for the stored objects.
+//!
+//! The expiry time of each key is stored in the `TTL_DB` database, with the
+//! key `<db length>:db:key`, so it works with any `Store`. `Model::get`,
+//! `Model::all` and `Model::iter` treat the expired objects as missing, and
+//! the expired objects are removed from the store with `purge` or with a
+//! `Sweeper` thread.
+//!
+//! Only the models that can expire, see `Model::expires`, read and write the
+//! `TTL_DB`, so the other models don't pay for it.
+
+use anyhow::Error;
+use anyhow::anyhow;
+use bincode::{serialize, deserialize};
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::mpsc::{channel, Sender, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::store::Store;
+#[cfg(feature = "async")]
+use crate::async_store::{AsyncStore, AsyncResult};
+use crate::signal::Signaler;
+use crate::signal::Signal;
+use crate::signal::SigType;
+
+/// Database where the expiry times are stored
+pub const TTL_DB: &str = "mdl-ttl";
+
+/// Removes the expired objects periodically, in a thread. The thread stops
+/// when the sweeper drops
+pub struct Sweeper {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Stores the expiry time of the `key`
+pub fn expire<S: Store>(store: &S, db: &str, key: &str, ttl: Duration) -> Result<(), Error> {
+    store.push(TTL_DB, &ttl_key(db, key), entry(key, ttl)?)
+}
+
+/// Removes the expiry time of the `key`, if there's one
+pub fn persist<S: Store>(store: &S, db: &str, key: &str) {
+    // stores return an error if the key doesn't exist
+    let _ = store.rm(TTL_DB, &ttl_key(db, key));
+}
+
+/// Returns true if the `key` has an expiry time and it's reached
+pub fn is_expired<S: Store>(store: &S, db: &str, key: &str) -> bool {
+    store.pull(TTL_DB, &ttl_key(db, key), decode)
+        .map(|(at, _)| at <= now())
+        .unwrap_or(false)
+}
+
+/// Returns the expired keys that starts with the prefix
+pub fn expired<S: Store>(store: &S, db: &str, prefix: &str) -> HashSet<String> {
+    let t = now();
+    expiry_times(store, db, prefix).into_iter()
+        .filter(|(at, _)| *at <= t)
+        .map(|(_, k)| k)
+        .collect()
+}
+
+/// Removes up to `max` expired objects of the `db`. Returns the `Delete`
+/// signals of the removed objects, with the removed value
+pub fn purge<S: Store>(store: &S, db: &'static str, max: usize) -> Result<Vec<Signal>, Error> {
+    let t = now();
+    let mut keys = expiry_times(store, db, "");
+    keys.retain(|(at, _)| *at <= t);
+    keys.truncate(max);
+
+    let mut signals = vec![];
+    for (_, key) in keys {
+        let old = store.pull(db, &key, |data| Ok(data.to_vec())).ok();
+        if old.is_some() {
+            store.rm(db, &key)?;
+        }
+        store.rm(TTL_DB, &ttl_key(db, &key))?;
+
+        let mut signal = Signal::new(SigType::Delete, &key);
+        signal.db = Some(db.to_string());
+        signal.old = old;
+        signals.push(signal);
+    }
+    Ok(signals)
+}
+
+impl Sweeper {
+    /// Starts the sweeper thread, that removes the expired objects of the
+    /// `dbs` each `interval`, in batches of `batch` objects
+    pub fn start<S>(store: S, dbs: &[&'static str], interval: Duration, batch: usize)
+        -> Result<Sweeper, Error>
+        where S: Store + Send + 'static {
+        Sweeper::spawn(store, dbs, interval, batch, |_| Ok(()))
+    }
+
+    /// Starts the sweeper thread that also emits the `Delete` signals of the
+    /// removed objects
+    pub fn start_sig<S, G>(store: S, sig: G, dbs: &[&'static str], interval: Duration,
+                           batch: usize) -> Result<Sweeper, Error>
+        where S: Store + Send + 'static,
+              G: Signaler + Send + 'static {
+        Sweeper::spawn(store, dbs, interval, batch, move |signal| sig.emit_signal(signal))
+    }
+
+    fn spawn<S, F>(store: S, dbs: &[&'static str], interval: Duration, batch: usize, emit: F)
+        -> Result<Sweeper, Error>
+        where S: Store + Send + 'static,
+              F: Fn(Signal) -> Result<(), Error> + Send + 'static {
+        if batch == 0 {
+            return Err(anyhow!("the sweeper batch can't be 0"));
+        }
+
+        let dbs = dbs.to_vec();
+        let (stop, rx) = channel::<()>();
+        let thread = thread::Builder::new()
+            .name("mdl-sweeper".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                    for db in dbs.iter() {
+                        // batches until there's nothing to remove
+                        while let Ok(signals) = purge(&store, db, batch) {
+                            let n = signals.len();
+                            for signal in signals {
+                                let _ = emit(signal);
+                            }
+                            if n < batch {
+                                break;
+                            }
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Sweeper { stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// Stops the sweeper thread and waits for it
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Async version of `expire`
+#[cfg(feature = "async")]
+pub fn expire_async<S: AsyncStore>(store: &S, db: &str, key: &str, ttl: Duration)
+    -> AsyncResult<()> {
+    match entry(key, ttl) {
+        Ok(value) => store.push_async(TTL_DB, &ttl_key(db, key), value),
+        Err(e) => Box::pin(async move { Err(e) }),
+    }
+}
+
+/// Async version of `persist`
+#[cfg(feature = "async")]
+pub fn persist_async<S: AsyncStore>(store: &S, db: &str, key: &str) -> AsyncResult<()> {
+    let rm = store.rm_async(TTL_DB, &ttl_key(db, key));
+    Box::pin(async move {
+        let _ = rm.await;
+        Ok(())
+    })
+}
+
+/// Async version of `is_expired`
+#[cfg(feature = "async")]
+pub fn is_expired_async<S: AsyncStore>(store: &S, db: &str, key: &str) -> AsyncResult<bool> {
+    let pull = store.pull_async(TTL_DB, &ttl_key(db, key), decode);
+    Box::pin(async move {
+        Ok(pull.await.map(|(at, _)| at <= now()).unwrap_or(false))
+    })
+}
+
+/// Async version of `expired`
+#[cfg(feature = "async")]
+pub fn expired_async<S: AsyncStore>(store: &S, db: &str, prefix: &str)
+    -> AsyncResult<HashSet<String>> {
+    let all = store.all_async(TTL_DB, &ttl_key(db, prefix), decode);
+    Box::pin(async move {
+        let t = now();
+        Ok(all.await.unwrap_or_default().into_iter()
+            .filter(|(at, _)| *at <= t)
+            .map(|(_, k)| k)
+            .collect())
+    })
+}
+
+/// (expiry time, key) of the keys that starts with the prefix
+fn expiry_times<S: Store>(store: &S, db: &str, prefix: &str) -> Vec<(u64, String)> {
+    // some stores return an error if there's no key with the prefix
+    store.all(TTL_DB, &ttl_key(db, prefix), decode).unwrap_or_default()
+}
+
+/// Key in the `TTL_DB`. The db length goes first, so the keys of different
+/// dbs can't collide, like the db "a" with the key "b:x" and the db "a:b"
+/// with the key "x"
+fn ttl_key(db: &str, key: &str) -> String {
+    format!("{}:{}:{}", db.len(), db, key)
+}
+
+fn entry(key: &str, ttl: Duration) -> Result<Vec<u8>, Error> {
+    // the huge durations never expire
+    let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+    let at = now().saturating_add(ttl);
+    Ok(serialize(&(at, key))?)
+}
+
+fn decode(data: &[u8]) -> Result<(u64, String), Error> {
+    Ok(deserialize(data)?)
+}
+
+/// Milliseconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
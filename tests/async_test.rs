@@ -0,0 +1,118 @@
+#![cfg(feature = "async")]
+
+use mdl::AsyncStore;
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Model;
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SigType;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs::remove_dir_all;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+static DB: &str = "/tmp/test.lmdb";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct B {
+    pub id: u32,
+    pub complex: Vec<String>,
+}
+impl Model for B {
+    fn key(&self) -> String {
+        format!("b:{}", self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Token {
+    pub id: u32,
+}
+impl Model for Token {
+    fn key(&self) -> String {
+        format!("token:{}", self.id)
+    }
+
+    fn ttl() -> Option<Duration> { Some(Duration::from_millis(50)) }
+}
+
+#[tokio::test]
+async fn async_store_test() {
+    let db = &format!("{}-async", DB);
+    let cache = Arc::new(Cache::new(db).unwrap());
+
+    for i in 1..10 {
+        let b = B{ id: i, complex: vec![] };
+        b.store_async(&cache).await.unwrap();
+    }
+
+    let b = B::get_async(&cache, "b:1").await.unwrap();
+    assert_eq!(b.id, 1);
+
+    let all = B::all_async(&cache, "b").await.unwrap();
+    assert_eq!(all.len(), 9);
+
+    b.delete_async(&cache).await.unwrap();
+    assert!(B::get_async(&cache, "b:1").await.is_err());
+
+    let raw = cache.pull_async(B::db(), "b:2", |data| Ok(data.to_vec())).await;
+    assert!(raw.is_ok());
+
+    let _ = remove_dir_all(db);
+}
+
+#[tokio::test]
+async fn async_bcache_test() {
+    let cache = BCache::new().unwrap();
+
+    let b = B{ id: 1, complex: vec!["async".to_string()] };
+    b.store_async(&cache).await.unwrap();
+
+    let b = B::get_async(&cache, "b:1").await.unwrap();
+    assert_eq!(b.complex, vec!["async".to_string()]);
+
+    cache.rm_async(B::db(), "b:1").await.unwrap();
+    assert!(cache.rm_async(B::db(), "b:1").await.is_err());
+}
+
+#[tokio::test]
+async fn async_ttl_test() {
+    let cache = BCache::new().unwrap();
+
+    Token { id: 1 }.store_async(&cache).await.unwrap();
+    Token { id: 2 }.store_async(&cache).await.unwrap();
+    assert!(Token::get_async(&cache, "token:1").await.is_ok());
+    assert_eq!(Token::all_async(&cache, "token").await.unwrap().len(), 2);
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(Token::get_async(&cache, "token:1").await.is_err());
+    assert!(Token::all_async(&cache, "token").await.unwrap().is_empty());
+
+    // storing again sets a new expiry time
+    Token { id: 1 }.store_async(&cache).await.unwrap();
+    assert!(Token::get_async(&cache, "token:1").await.is_ok());
+    assert_eq!(Token::all_async(&cache, "token").await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn signal_stream_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    let mut stream = sig.stream("stream").unwrap();
+
+    sig.emit(SigType::Update, "other").unwrap();
+    sig.emit(SigType::Update, "stream:1").unwrap();
+    sig.emit(SigType::Delete, "stream:2").unwrap();
+
+    let mut names = vec![];
+    names.push(stream.recv().await.unwrap().name);
+    names.push(stream.recv().await.unwrap().name);
+    names.sort();
+
+    assert_eq!(names, vec!["stream:1".to_string(), "stream:2".to_string()]);
+}
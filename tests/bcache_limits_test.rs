@@ -0,0 +1,142 @@
+use mdl::BCache;
+use mdl::Limits;
+use mdl::Pattern;
+use mdl::Policy;
+use mdl::SigType;
+use mdl::SignalerAsync;
+use mdl::Store;
+
+use std::sync::{Arc, Mutex};
+
+// iter doesn't count as a use, so it doesn't change the eviction order
+fn has(cache: &BCache, db: &'static str, key: &str) -> bool {
+    !cache.all(db, key, |data| Ok(data.to_vec())).unwrap().is_empty()
+}
+
+#[test]
+fn lru_entries_test() {
+    let cache = BCache::with_limits(Limits::entries(3, Policy::Lru)).unwrap();
+    cache.push("db", "a", vec![1]).unwrap();
+    cache.push("db", "b", vec![2]).unwrap();
+    cache.push("db", "c", vec![3]).unwrap();
+
+    // a is used so b is the least recently used
+    cache.pull("db", "a", |_| Ok(())).unwrap();
+    cache.push("db", "d", vec![4]).unwrap();
+
+    assert!(has(&cache, "db", "a"));
+    assert!(!has(&cache, "db", "b"));
+    assert!(has(&cache, "db", "c"));
+    assert!(has(&cache, "db", "d"));
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.evictions, 1);
+}
+
+#[test]
+fn lfu_entries_test() {
+    let cache = BCache::with_limits(Limits::entries(2, Policy::Lfu)).unwrap();
+    cache.push("db", "a", vec![1]).unwrap();
+    cache.push("db", "b", vec![2]).unwrap();
+    for _ in 0..3 {
+        cache.pull("db", "a", |_| Ok(())).unwrap();
+    }
+    cache.pull("db", "b", |_| Ok(())).unwrap();
+
+    // b is the most recent but the least used
+    cache.push("db", "c", vec![3]).unwrap();
+    assert!(has(&cache, "db", "a"));
+    assert!(!has(&cache, "db", "b"));
+    assert!(has(&cache, "db", "c"));
+}
+
+#[test]
+fn lfu_update_test() {
+    let cache = BCache::with_limits(Limits::entries(2, Policy::Lfu)).unwrap();
+    cache.push("db", "a", vec![1]).unwrap();
+    cache.push("db", "b", vec![2]).unwrap();
+    for _ in 0..3 {
+        cache.pull("db", "a", |_| Ok(())).unwrap();
+    }
+    cache.pull("db", "b", |_| Ok(())).unwrap();
+
+    // the updated hot key keeps its hits
+    cache.push("db", "a", vec![10]).unwrap();
+    cache.push("db", "c", vec![3]).unwrap();
+    assert!(has(&cache, "db", "a"));
+    assert!(!has(&cache, "db", "b"));
+    assert!(has(&cache, "db", "c"));
+}
+
+#[test]
+fn bytes_test() {
+    // each entry is "db:kN" + 10 bytes = 15 bytes
+    let cache = BCache::with_limits(Limits::bytes(40, Policy::Lru)).unwrap();
+    for i in 0..5 {
+        cache.push("db", &format!("k{}", i), vec![0; 10]).unwrap();
+    }
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.bytes, 30);
+    assert_eq!(stats.evictions, 3);
+    assert_eq!(stats.evicted_bytes, 45);
+    assert!(has(&cache, "db", "k3"));
+    assert!(has(&cache, "db", "k4"));
+
+    // replacing a value doesn't count twice
+    cache.push("db", "k4", vec![0; 5]).unwrap();
+    assert_eq!(cache.stats().bytes, 25);
+    cache.rm("db", "k4").unwrap();
+    assert_eq!(cache.stats().entries, 1);
+}
+
+#[test]
+fn db_limits_test() {
+    let limits = Limits::entries(10, Policy::Lru).db("small", 1);
+    let cache = BCache::with_limits(limits).unwrap();
+    cache.push("big", "a", vec![1]).unwrap();
+    cache.push("small", "a", vec![1]).unwrap();
+    cache.push("small", "b", vec![1]).unwrap();
+    cache.push("big", "b", vec![1]).unwrap();
+
+    assert!(has(&cache, "big", "a"));
+    assert!(has(&cache, "big", "b"));
+    assert!(!has(&cache, "small", "a"));
+    assert!(has(&cache, "small", "b"));
+    assert_eq!(cache.stats().evictions, 1);
+}
+
+#[test]
+fn unlimited_test() {
+    let cache = BCache::new().unwrap();
+    for i in 0..100 {
+        cache.push("db", &format!("k{}", i), vec![0; 10]).unwrap();
+    }
+    assert_eq!(cache.stats().evictions, 0);
+    assert!(has(&cache, "db", "k0"));
+}
+
+#[test]
+fn evict_signals_test() {
+    let cache = BCache::with_limits(Limits::entries(1, Policy::Lru)).unwrap();
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    cache.evict_signals(sig.clone());
+
+    let deleted = Arc::new(Mutex::new(vec![]));
+    let d = deleted.clone();
+    let _sub = sig.subscribe(Pattern::glob("*"), Box::new(move |s| {
+        if let SigType::Delete = s.type_ {
+            d.lock().unwrap().push((s.db, s.name, s.old));
+        }
+    })).unwrap();
+
+    cache.push("db", "a:1", vec![1]).unwrap();
+    cache.push("db", "a:2", vec![2]).unwrap();
+    sig.stop();
+
+    let deleted = deleted.lock().unwrap();
+    assert_eq!(*deleted, vec![(Some("db".to_string()), "a:1".to_string(), Some(vec![1]))]);
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use std::fs::remove_dir_all;
 
-static DB: &'static str = "/tmp/test.lmdb";
+static DB: &str = "/tmp/test.lmdb";
 
 #[derive(Serialize, Deserialize, Debug)]
 struct A {
@@ -0,0 +1,155 @@
+#![cfg(any(feature = "zstd", feature = "lz4"))]
+
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Codec;
+use mdl::Compressed;
+use mdl::Model;
+use mdl::Store;
+use mdl::compress::{compress, decompress, decompress_max};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct B {
+    pub id: u32,
+    pub complex: Vec<String>,
+}
+impl Model for B {
+    fn key(&self) -> String {
+        format!("b:{}", self.id)
+    }
+}
+
+fn big(id: u32) -> B {
+    B { id, complex: (0..200).map(|i| format!("value {}", i % 10)).collect() }
+}
+
+fn codecs() -> Vec<Codec> {
+    vec![
+        #[cfg(feature = "zstd")]
+        Codec::Zstd(3),
+        #[cfg(feature = "lz4")]
+        Codec::Lz4,
+    ]
+}
+
+fn raw_size<S: Store>(store: &S, key: &str) -> usize {
+    store.pull("default", key, |v| Ok(v.len())).unwrap()
+}
+
+#[test]
+fn store_test() {
+    for codec in codecs() {
+        let path = format!("/tmp/test-compress-{:?}.lmdb", codec);
+        let _ = fs::remove_dir_all(&path);
+        let store = Compressed::new(Cache::new(&path).unwrap(), codec);
+
+        let b = big(1);
+        let small = B { id: 2, complex: vec!["x".to_string()] };
+        b.store(&store).unwrap();
+        small.store(&store).unwrap();
+
+        assert_eq!(B::get(&store, "b:1").unwrap(), b);
+        assert_eq!(B::get(&store, "b:2").unwrap(), small);
+        assert_eq!(B::all(&store, "b:").unwrap(), vec![big(1), small]);
+
+        // the big value is compressed, the small one is stored raw
+        let size = bincode::serialize(&big(1)).unwrap().len();
+        assert!(raw_size(store.inner(), "b:1") < size / 2);
+        let size = bincode::serialize(&B::get(&store, "b:2").unwrap()).unwrap().len();
+        assert_eq!(raw_size(store.inner(), "b:2"), size);
+
+        B::get(&store, "b:1").unwrap().delete(&store).unwrap();
+        assert!(B::get(&store, "b:1").is_err());
+    }
+}
+
+#[test]
+fn threshold_test() {
+    for codec in codecs() {
+        let store = Compressed::new(BCache::new().unwrap(), codec).threshold(100_000);
+        big(1).store(&store).unwrap();
+        let size = bincode::serialize(&big(1)).unwrap().len();
+        assert_eq!(raw_size(store.inner(), "b:1"), size);
+
+        // values written with other thresholds and codecs can be read
+        let store = Compressed::new(store.into_inner(), codecs()[0]).threshold(0);
+        big(2).store(&store).unwrap();
+        assert_eq!(B::all(&store, "b:").unwrap(), vec![big(1), big(2)]);
+    }
+}
+
+#[test]
+fn header_test() {
+    for codec in codecs() {
+        let data = vec![7; 1000];
+        let compressed = compress(codec, 10, &data).unwrap();
+        assert!(compressed.len() < 100);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        // values that don't get smaller are stored raw
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(compress(codec, 10, &data).unwrap(), data);
+
+        // raw values starting with the magic keep the header
+        let data = b"MDLZ value".to_vec();
+        let stored = compress(codec, 1000, &data).unwrap();
+        assert_eq!(stored.len(), data.len() + 6);
+        assert_eq!(decompress(&stored).unwrap(), data);
+    }
+
+    // values without the header are read raw
+    assert!(decompress(&[]).unwrap().is_empty());
+    assert_eq!(decompress(&[9, 1, 2]).unwrap(), vec![9, 1, 2]);
+    assert_eq!(decompress(b"MDLZ\x02\x01").unwrap(), b"MDLZ\x02\x01");
+    assert!(decompress(b"MDLZ\x01\x09").is_err());
+
+    let store = Compressed::new(BCache::new().unwrap(), codecs()[0]);
+    store.inner().push("default", "b:1", b"MDLZ\x01\x09".to_vec()).unwrap();
+    assert!(B::get(&store, "b:1").is_err());
+    assert!(B::all(&store, "b:").is_err());
+}
+
+#[test]
+fn old_values_test() {
+    for codec in codecs() {
+        let path = format!("/tmp/test-compress-old-{:?}.lmdb", codec);
+        let _ = fs::remove_dir_all(&path);
+        let cache = Cache::new(&path).unwrap();
+
+        // written before enabling the compression, the first bytes of the
+        // ids match the codec bytes
+        for id in 0..3 {
+            big(id).store(&cache).unwrap();
+        }
+
+        let store = Compressed::new(cache, codec);
+        big(3).store(&store).unwrap();
+        assert_eq!(B::get(&store, "b:1").unwrap(), big(1));
+        assert_eq!(B::all(&store, "b:").unwrap(), (0..4).map(big).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn max_size_test() {
+    for codec in codecs() {
+        let data = vec![0; 100_000];
+        let compressed = compress(codec, 10, &data).unwrap();
+        assert!(compressed.len() < 1000);
+        assert!(decompress_max(&compressed, 99_999).is_err());
+        assert_eq!(decompress_max(&compressed, 100_000).unwrap(), data);
+
+        // bigger values are stored raw, so they can be read
+        let store = Compressed::new(BCache::new().unwrap(), codec).max_size(1000);
+        big(1).store(&store).unwrap();
+        let size = bincode::serialize(&big(1)).unwrap().len();
+        assert_eq!(raw_size(store.inner(), "b:1"), size);
+        assert_eq!(B::get(&store, "b:1").unwrap(), big(1));
+
+        store.inner().push("default", "b:2", compressed).unwrap();
+        assert!(store.pull("default", "b:2", |v| Ok(v.len())).is_err());
+    }
+}
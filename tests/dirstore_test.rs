@@ -0,0 +1,164 @@
+#![cfg(feature = "dirstore")]
+
+use mdl::dirstore::{self, Format};
+use mdl::DirStore;
+use mdl::Model;
+use mdl::Store;
+use mdl::ttl;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Config {
+    pub name: String,
+    pub value: u32,
+}
+impl Model for Config {
+    fn key(&self) -> String {
+        format!("config:{}", self.name)
+    }
+
+    fn db() -> &'static str { "settings" }
+
+    fn tob(&self) -> Result<Vec<u8>, Error> { dirstore::to_json(self) }
+
+    fn fromb(data: &[u8]) -> Result<Self, Error> { dirstore::from_json(data) }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Theme {
+    pub name: String,
+    pub colors: Vec<String>,
+}
+impl Model for Theme {
+    fn key(&self) -> String {
+        format!("theme/{}", self.name)
+    }
+
+    fn tob(&self) -> Result<Vec<u8>, Error> { dirstore::to_toml(self) }
+
+    fn fromb(data: &[u8]) -> Result<Self, Error> { dirstore::from_toml(data) }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Binary {
+    pub id: u32,
+}
+impl Model for Binary {
+    fn key(&self) -> String {
+        format!("binary:{}", self.id)
+    }
+}
+
+fn config(name: &str, value: u32) -> Config {
+    Config { name: name.to_string(), value }
+}
+
+fn dir(name: &str) -> String {
+    let path = format!("/tmp/test-dirstore-{}", name);
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+#[test]
+fn json_test() {
+    let path = &dir("json");
+    let store = DirStore::new(path, Format::Json).unwrap();
+
+    for (name, value) in &[("b", 2), ("a", 1), ("a:b/c", 3), ("b1", 4)] {
+        config(name, *value).store(&store).unwrap();
+    }
+    assert_eq!(Config::get(&store, "config:a:b/c").unwrap(), config("a:b/c", 3));
+
+    // a file per key, with the unsafe characters escaped
+    let file = format!("{}/settings/config%3Aa%3Ab%2Fc.json", path);
+    assert_eq!(store.file("settings", "config:a:b/c").to_str().unwrap(), file);
+    let text = fs::read_to_string(&file).unwrap();
+    assert!(text.contains("\"value\": 3"));
+
+    // sorted by key
+    let names: Vec<String> = Config::all(&store, "config:").unwrap()
+        .into_iter().map(|c| c.name).collect();
+    assert_eq!(names, vec!["a", "a:b/c", "b", "b1"]);
+    assert_eq!(Config::all(&store, "config:a").unwrap().len(), 2);
+    assert!(Config::all(&store, "other").unwrap().is_empty());
+
+    // edited by hand
+    fs::write(&file, "{\"name\": \"a:b/c\", \"value\": 30}").unwrap();
+    assert_eq!(Config::get(&store, "config:a:b/c").unwrap().value, 30);
+
+    config("a", 1).delete(&store).unwrap();
+    assert!(Config::get(&store, "config:a").is_err());
+    assert!(store.rm("settings", "config:a").is_err());
+
+    // no temporary files left
+    let tmp = fs::read_dir(format!("{}/settings", path)).unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_str().unwrap().starts_with('.'))
+        .count();
+    assert_eq!(tmp, 0);
+    let _ = fs::remove_dir_all(path);
+}
+
+#[test]
+fn toml_test() {
+    let path = &dir("toml");
+    let store = DirStore::new(path, Format::Toml).unwrap();
+
+    let theme = Theme { name: "dark".to_string(), colors: vec!["#000".to_string()] };
+    theme.store(&store).unwrap();
+    let text = fs::read_to_string(format!("{}/default/theme%2Fdark.toml", path)).unwrap();
+    assert!(text.contains("name = 'dark'"));
+    assert_eq!(Theme::get(&store, "theme/dark").unwrap(), theme);
+    let _ = fs::remove_dir_all(path);
+}
+
+#[test]
+fn invalid_value_test() {
+    let path = &dir("invalid");
+    let store = DirStore::new(path, Format::Json).unwrap();
+
+    // bincode models can't be stored
+    let err = Binary { id: 1 }.store(&store).err().unwrap();
+    assert!(err.to_string().contains("Model::tob"));
+
+    // other files in the directory are ignored
+    fs::create_dir_all(format!("{}/settings", path)).unwrap();
+    fs::write(format!("{}/settings/README", path), "notes").unwrap();
+    fs::write(format!("{}/settings/.config%3Ax.json.swp", path), "{}").unwrap();
+    config("x", 1).store(&store).unwrap();
+    assert_eq!(Config::all(&store, "").unwrap().len(), 1);
+    let _ = fs::remove_dir_all(path);
+}
+
+#[test]
+fn ttl_test() {
+    let path = &dir("ttl");
+    let store = DirStore::new(path, Format::Json).unwrap();
+
+    // the expiry times are encoded with bincode
+    ttl::expire(&store, "settings", "config:a", Duration::from_secs(60)).unwrap();
+    ttl::expire(&store, "settings", "config:b", Duration::from_millis(0)).unwrap();
+    assert!(!ttl::is_expired(&store, "settings", "config:a"));
+    assert!(ttl::is_expired(&store, "settings", "config:b"));
+    let _ = fs::remove_dir_all(path);
+}
+
+#[test]
+fn tmp_files_test() {
+    let path = &dir("tmp");
+    let store = DirStore::new(path, Format::Json).unwrap();
+    config("a", 1).store(&store).unwrap();
+    drop(store);
+
+    // an interrupted write
+    let tmp = format!("{}/settings/.config%3Ab.1-0.tmp", path);
+    fs::write(&tmp, "{\"name\": ").unwrap();
+    let store = DirStore::new(path, Format::Json).unwrap();
+    assert!(fs::metadata(&tmp).is_err());
+    assert_eq!(Config::all(&store, "").unwrap(), vec![config("a", 1)]);
+    let _ = fs::remove_dir_all(path);
+}
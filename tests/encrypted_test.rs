@@ -0,0 +1,139 @@
+#![cfg(feature = "encryption")]
+
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::Encrypted;
+use mdl::Model;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Token {
+    pub user: String,
+    pub token: String,
+}
+impl Model for Token {
+    fn key(&self) -> String {
+        format!("token:{}", self.user)
+    }
+}
+
+const KEY1: [u8; 32] = [1; 32];
+const KEY2: [u8; 32] = [2; 32];
+
+fn token(user: &str) -> Token {
+    Token { user: user.to_string(), token: format!("secret-{}", user) }
+}
+
+fn raw<S: Store>(store: &S) -> Vec<(String, Vec<u8>)> {
+    let items = RefCell::new(vec![]);
+    store.iter_kv("default", "", |k, v| {
+        items.borrow_mut().push((k.to_string(), v.to_vec()));
+        Continue(true)
+    }).unwrap();
+    items.into_inner()
+}
+
+fn contains(data: &[u8], s: &str) -> bool {
+    data.windows(s.len()).any(|w| w == s.as_bytes())
+}
+
+#[test]
+fn values_test() {
+    let path = "/tmp/test-encrypted.lmdb";
+    let _ = fs::remove_dir_all(path);
+    let store = Encrypted::new(Cache::new(path).unwrap(), &KEY1);
+    for user in &["bob", "alice", "carol"] {
+        token(user).store(&store).unwrap();
+    }
+    Token { user: "x".to_string(), token: "".to_string() }.store(&store).unwrap();
+
+    assert_eq!(Token::get(&store, "token:bob").unwrap(), token("bob"));
+    let all = Token::all(&store, "token:").unwrap();
+    assert_eq!(all.iter().map(|t| t.user.as_str()).collect::<Vec<_>>(),
+               vec!["alice", "bob", "carol", "x"]);
+
+    // the values are encrypted, with a different nonce each time
+    let before = raw(store.inner());
+    assert!(before.iter().all(|(_, v)| !contains(v, "secret")));
+    token("bob").store(&store).unwrap();
+    let after = raw(store.inner());
+    assert_eq!(before[0], after[0]);
+    assert_ne!(before[1], after[1]);
+
+    // a value can't be moved to another key
+    store.inner().push("default", "token:alice", after[1].1.clone()).unwrap();
+    assert!(Token::get(&store, "token:alice").is_err());
+
+    Token::get(&store, "token:bob").unwrap().delete(&store).unwrap();
+    assert!(Token::get(&store, "token:bob").is_err());
+
+    // wrong key
+    let other = Encrypted::new(BCache::new().unwrap(), &KEY2);
+    other.inner().push("default", "token:carol", after[2].1.clone()).unwrap();
+    assert!(Token::get(&other, "token:carol").is_err());
+}
+
+#[test]
+fn hashed_keys_test() {
+    let store = Encrypted::new(BCache::new().unwrap(), &KEY1).hash_keys(b"names");
+    for user in &["bob", "alice", "carol"] {
+        token(user).store(&store).unwrap();
+    }
+    let mut other = token("z");
+    other.user = "other".to_string();
+    store.push("default", "other", other.tob().unwrap()).unwrap();
+
+    let items = raw(store.inner());
+    assert_eq!(items.len(), 4);
+    assert!(items.iter().all(|(k, _)| k.len() == 32 && !k.contains("token")));
+
+    assert_eq!(Token::get(&store, "token:alice").unwrap(), token("alice"));
+    let all = Token::all(&store, "token:").unwrap();
+    assert_eq!(all, vec![token("alice"), token("bob"), token("carol")]);
+
+    Token::get(&store, "token:bob").unwrap().delete(&store).unwrap();
+    assert_eq!(Token::all(&store, "token:").unwrap().len(), 2);
+
+    // other secrets don't find the keys
+    let store = Encrypted::new(store.into_inner(), &KEY1).hash_keys(b"other");
+    assert!(Token::get(&store, "token:alice").is_err());
+}
+
+#[test]
+fn rotate_test() {
+    let mut store = Encrypted::new(BCache::new().unwrap(), &KEY1).hash_keys(b"names");
+    for user in &["bob", "alice", "carol"] {
+        token(user).store(&store).unwrap();
+    }
+    let before = raw(store.inner());
+
+    assert_eq!(store.rotate(&KEY2, &["default", "empty"]).unwrap(), 3);
+    let after = raw(store.inner());
+    // same names, new values
+    assert_eq!(before.iter().map(|i| &i.0).collect::<Vec<_>>(),
+               after.iter().map(|i| &i.0).collect::<Vec<_>>());
+    assert!(before.iter().zip(after.iter()).all(|(a, b)| a.1 != b.1));
+    assert_eq!(Token::all(&store, "token:").unwrap().len(), 3);
+
+    // already rotated
+    assert_eq!(store.rotate(&KEY2, &["default"]).unwrap(), 0);
+
+    // only the new key can read the values
+    let cache = store.into_inner();
+    let old = Encrypted::new(cache, &KEY1).hash_keys(b"names");
+    assert!(Token::get(&old, "token:bob").is_err());
+    let new = Encrypted::new(old.into_inner(), &KEY2).hash_keys(b"names");
+    assert_eq!(Token::get(&new, "token:bob").unwrap(), token("bob"));
+
+    // values written with a previous key
+    let prev = Encrypted::new(new.into_inner(), &KEY1).hash_keys(b"names");
+    token("dave").store(&prev).unwrap();
+    let store = Encrypted::new(prev.into_inner(), &KEY2).hash_keys(b"names").old_key(&KEY1);
+    assert_eq!(Token::all(&store, "token:").unwrap().len(), 4);
+}
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Error};
+
+use mdl::BCache;
+use mdl::Change;
+use mdl::Continue;
+use mdl::History;
+use mdl::Model;
+use mdl::SignalerSync;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Todo {
+    pub id: u32,
+    pub text: String,
+}
+impl Model for Todo {
+    fn key(&self) -> String {
+        format!("todo:{}", self.id)
+    }
+}
+
+fn todo(id: u32, text: &str) -> Todo {
+    Todo { id, text: text.to_string() }
+}
+
+#[test]
+fn undo_redo_test() {
+    let store = History::new(BCache::new().unwrap(), 10);
+    assert!(!store.undo().unwrap());
+
+    todo(1, "first").store(&store).unwrap();
+    todo(1, "second").store(&store).unwrap();
+    todo(2, "other").store(&store).unwrap();
+    todo(1, "").delete(&store).unwrap();
+
+    assert!(Todo::get(&store, "todo:1").is_err());
+
+    assert!(store.undo().unwrap());
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "second");
+    assert!(store.undo().unwrap());
+    assert!(Todo::get(&store, "todo:2").is_err());
+    assert!(store.undo().unwrap());
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "first");
+    assert!(store.undo().unwrap());
+    assert!(Todo::get(&store, "todo:1").is_err());
+    assert!(!store.undo().unwrap());
+
+    assert!(store.redo().unwrap());
+    assert!(store.redo().unwrap());
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "second");
+
+    // a new modification clears the redo steps
+    todo(3, "new").store(&store).unwrap();
+    assert!(!store.can_redo());
+    assert!(!store.redo().unwrap());
+    assert!(Todo::get(&store, "todo:2").is_err());
+}
+
+#[test]
+fn step_test() {
+    let store = History::new(BCache::new().unwrap(), 10);
+    todo(1, "first").store(&store).unwrap();
+
+    store.step(|s| {
+        todo(1, "changed").store(s)?;
+        todo(2, "added").store(s)?;
+        store.step(|s| todo(3, "nested").store(s))?;
+        todo(1, "").delete(s)
+    }).unwrap();
+    assert_eq!(Todo::all(&store, "todo").unwrap().len(), 2);
+
+    assert!(store.undo().unwrap());
+    assert_eq!(Todo::all(&store, "todo").unwrap(), vec![todo(1, "first")]);
+
+    assert!(store.redo().unwrap());
+    assert_eq!(Todo::all(&store, "todo").unwrap(), vec![todo(2, "added"), todo(3, "nested")]);
+}
+
+#[test]
+fn depth_test() {
+    let store = History::new(BCache::new().unwrap(), 3);
+    for i in 0..10 {
+        todo(1, &format!("{}", i)).store(&store).unwrap();
+    }
+
+    while store.undo().unwrap() {}
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "6");
+}
+
+#[test]
+fn replay_signals_test() {
+    let store = History::new(BCache::new().unwrap(), 10);
+    let sig = SignalerSync::new();
+    let changes = Arc::new(Mutex::new(vec![]));
+
+    let c = changes.clone();
+    let _sub = Todo::subscribe(&sig, "todo", move |change| {
+        let desc = match change {
+            Change::Created(t) => format!("created {}", t.text),
+            Change::Updated { old, new } => format!("updated {} {}", old.text, new.text),
+            Change::Deleted(key) => format!("deleted {}", key),
+        };
+        c.lock().unwrap().push(desc);
+    });
+
+    todo(1, "first").store(&store).unwrap();
+    todo(1, "second").store(&store).unwrap();
+
+    store.undo_sig(&sig).unwrap();
+    store.undo_sig(&sig).unwrap();
+    store.redo_sig(&sig).unwrap();
+    sig.drain();
+
+    assert_eq!(*changes.lock().unwrap(), vec![
+        "updated second first".to_string(),
+        "deleted todo:1".to_string(),
+        "created first".to_string(),
+    ]);
+}
+
+/// Store where the writes fail while `fail` is set
+struct Failing {
+    store: BCache,
+    fail: Arc<AtomicBool>,
+}
+
+impl Store for Failing {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        match self.fail.load(Ordering::SeqCst) {
+            true => Err(anyhow!("push failed")),
+            false => self.store.push(db, key, value),
+        }
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.store.pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.store.iter(db, prefix, f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.store.rm(db, key)
+    }
+}
+
+#[test]
+fn replay_errors_test() {
+    let fail = Arc::new(AtomicBool::new(false));
+    let store = History::new(Failing { store: BCache::new().unwrap(), fail: fail.clone() }, 10);
+
+    todo(1, "first").store(&store).unwrap();
+    todo(1, "second").store(&store).unwrap();
+
+    // the failed steps are kept
+    fail.store(true, Ordering::SeqCst);
+    assert!(store.undo().is_err());
+    fail.store(false, Ordering::SeqCst);
+    assert!(store.undo().unwrap());
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "first");
+
+    fail.store(true, Ordering::SeqCst);
+    assert!(store.redo().is_err());
+    assert!(store.can_redo());
+    fail.store(false, Ordering::SeqCst);
+    assert!(store.redo().unwrap());
+    assert_eq!(Todo::get(&store, "todo:1").unwrap().text, "second");
+    assert!(!store.can_redo());
+
+    assert!(store.undo().unwrap());
+    assert!(store.undo().unwrap());
+    assert!(!store.undo().unwrap());
+}
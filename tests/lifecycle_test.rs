@@ -0,0 +1,163 @@
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SignalerSync;
+use mdl::SigType;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+thread_local! {
+    // dropped when the thread that runs the callback terminates
+    static LOOP: RefCell<Option<Sender<()>>> = const { RefCell::new(None) };
+}
+
+#[test]
+fn stop_joins_thread_test() {
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    assert!(sig.is_running());
+
+    let (tx, rx) = channel::<()>();
+    let tx = Mutex::new(Some(tx));
+    sig.subscribe("signal", Box::new(move |_sig| {
+        let tx = tx.lock().unwrap().take();
+        LOOP.with(|l| *l.borrow_mut() = tx);
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "signal").unwrap();
+
+    // stopping from a clone
+    sig.clone().stop();
+    assert!(!sig.is_running());
+    assert!(!sig.status().running);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn stop_flushes_test() {
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    let counter = Arc::new(Mutex::new(0));
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap().detach();
+    let c2 = counter.clone();
+    sig.subscribe_coalesced("row", Duration::from_secs(60), Box::new(move |_sig| {
+        *c2.lock().unwrap() += 10;
+    })).unwrap().detach();
+
+    for _ in 0..100 {
+        sig.emit(SigType::Update, "signal").unwrap();
+    }
+    sig.emit(SigType::Update, "row:1").unwrap();
+    sig.stop();
+
+    assert_eq!(*counter.lock().unwrap(), 110);
+    assert_eq!(sig.status().pending, 0);
+}
+
+#[test]
+fn restart_test() {
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    let counter = Arc::new(Mutex::new(0));
+
+    let c1 = counter.clone();
+    let _sub = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap();
+
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.stop();
+    assert_eq!(*counter.lock().unwrap(), 1);
+
+    // signals emitted while stopped are dispatched after the start
+    sig.emit(SigType::Update, "signal").unwrap();
+    assert_eq!(*counter.lock().unwrap(), 1);
+
+    sig.start().unwrap();
+    sig.start().unwrap();
+    assert!(sig.is_running());
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.stop();
+    assert_eq!(*counter.lock().unwrap(), 3);
+}
+
+#[test]
+fn sync_restart_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+    assert!(sig.is_running());
+
+    let c1 = counter.clone();
+    let _sub = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap();
+
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.stop();
+    assert!(!sig.is_running());
+    assert_eq!(*counter.borrow(), 1);
+
+    sig.emit(SigType::Update, "signal").unwrap();
+    assert!(!sig.signal_loop_sync());
+    assert_eq!(sig.drain(), 0);
+    assert!(sig.wait_for("signal", Duration::from_secs(10)).is_none());
+
+    sig.start();
+    assert_eq!(sig.drain(), 1);
+    assert_eq!(*counter.borrow(), 2);
+}
+
+#[test]
+fn stop_from_callback_test() {
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    let counter = Arc::new(Mutex::new(0));
+    let returned = Arc::new(Mutex::new(false));
+
+    let (stopped, stopped_rx) = channel::<()>();
+    let (resume, resume_rx) = channel::<()>();
+    let stopped = Mutex::new(stopped);
+    let resume_rx = Mutex::new(resume_rx);
+    let s = sig.clone();
+    let r = returned.clone();
+    sig.subscribe("stop", Box::new(move |_sig| {
+        s.stop();
+        // the loop can't exit while the callback waits for it
+        assert!(s.start().is_err());
+        stopped.lock().unwrap().send(()).unwrap();
+        resume_rx.lock().unwrap().recv().unwrap();
+        *r.lock().unwrap() = true;
+    })).unwrap().detach();
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "stop").unwrap();
+    stopped_rx.recv().unwrap();
+
+    // start waits for the old loop thread, so there's only one loop
+    let s = sig.clone();
+    let r = returned.clone();
+    let starter = std::thread::spawn(move || {
+        s.start().unwrap();
+        *r.lock().unwrap()
+    });
+    resume.send(()).unwrap();
+    assert!(starter.join().unwrap());
+    assert!(sig.is_running());
+
+    for _ in 0..10 {
+        sig.emit(SigType::Update, "signal").unwrap();
+    }
+    sig.stop();
+    assert_eq!(*counter.lock().unwrap(), 10);
+}
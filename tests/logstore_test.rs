@@ -0,0 +1,224 @@
+use mdl::Continue;
+use mdl::LogStore;
+use mdl::Model;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct B {
+    pub id: u32,
+    pub complex: Vec<String>,
+}
+impl Model for B {
+    fn key(&self) -> String {
+        format!("b:{}", self.id)
+    }
+}
+
+fn path(name: &str) -> String {
+    let path = format!("/tmp/test-{}.log", name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn size(path: &str) -> u64 {
+    fs::metadata(path).unwrap().len()
+}
+
+#[test]
+fn model_test() {
+    let path = &path("model");
+    let store = LogStore::new(path).unwrap();
+    for i in 1..10 {
+        B { id: i, complex: vec![] }.store(&store).unwrap();
+    }
+    store.push("other", "b:1", vec![]).unwrap();
+
+    let mut b = B::get(&store, "b:1").unwrap();
+    b.complex.push("UPDATED".to_string());
+    b.store(&store).unwrap();
+    B { id: 2, complex: vec![] }.delete(&store).unwrap();
+    assert!(B::get(&store, "b:2").is_err());
+    assert!(store.rm("db", "b:2").is_err());
+
+    let all = B::all(&store, "b").unwrap();
+    assert_eq!(all.iter().map(|b| b.id).collect::<Vec<_>>(), vec![1, 3, 4, 5, 6, 7, 8, 9]);
+    assert_eq!(all[0].complex.len(), 1);
+
+    let n = std::cell::RefCell::new(0);
+    B::iter(&store, "b", |_| {
+        *n.borrow_mut() += 1;
+        Continue(*n.borrow() < 2)
+    }).unwrap();
+    assert_eq!(*n.borrow(), 2);
+
+    // the index is rebuilt from the file
+    drop(store);
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(B::all(&store, "b").unwrap().len(), 8);
+    assert_eq!(B::get(&store, "b:1").unwrap().complex.len(), 1);
+    assert!(B::get(&store, "b:2").is_err());
+    assert!(store.pull("other", "b:1", |_| Ok(())).is_ok());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn torn_write_test() {
+    let path = &path("torn");
+    let store = LogStore::new(path).unwrap();
+    store.push("db", "a", vec![1; 10]).unwrap();
+    store.push("db", "b", vec![2; 10]).unwrap();
+    drop(store);
+    let good = size(path);
+
+    // half written record
+    let store = LogStore::new(path).unwrap();
+    store.push("db", "c", vec![3; 10]).unwrap();
+    drop(store);
+    let file = OpenOptions::new().write(true).open(path).unwrap();
+    file.set_len(size(path) - 5).unwrap();
+
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(size(path), good);
+    assert!(store.pull("db", "b", |_| Ok(())).is_ok());
+    assert!(store.pull("db", "c", |_| Ok(())).is_err());
+
+    // the store is still usable after the truncation
+    store.push("db", "c", vec![3; 10]).unwrap();
+    drop(store);
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(store.pull("db", "c", |v| Ok(v.to_vec())).unwrap(), vec![3; 10]);
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn corrupted_test() {
+    let path = &path("corrupted");
+    let store = LogStore::new(path).unwrap();
+    store.push("db", "a", vec![1; 10]).unwrap();
+    drop(store);
+    let good = size(path);
+
+    // garbage after the last record
+    let mut file = OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(&[0xff; 40]).unwrap();
+    drop(file);
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(size(path), good);
+    store.push("db", "b", vec![2; 10]).unwrap();
+    drop(store);
+
+    // a flipped bit in the last value
+    let mut data = fs::read(path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    fs::write(path, &data).unwrap();
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(size(path), good);
+    assert!(store.pull("db", "a", |_| Ok(())).is_ok());
+    assert!(store.pull("db", "b", |_| Ok(())).is_err());
+    drop(store);
+
+    fs::write(path, b"not a log").unwrap();
+    assert!(LogStore::new(path).is_err());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn middle_corruption_test() {
+    let path = &path("middle");
+    let store = LogStore::new(path).unwrap();
+    store.push("db", "a", vec![1; 10]).unwrap();
+    store.push("db", "b", vec![2; 10]).unwrap();
+    store.push("db", "c", vec![3; 10]).unwrap();
+    drop(store);
+    let len = size(path);
+
+    // a flipped bit in the first value, the next records are valid
+    let mut data = fs::read(path).unwrap();
+    let i = data.windows(10).position(|w| w == [1; 10]).unwrap();
+    data[i] ^= 0x10;
+    fs::write(path, &data).unwrap();
+
+    let err = LogStore::new(path).err().unwrap();
+    assert!(err.to_string().contains("corrupted record"));
+    // the valid records are not truncated
+    assert_eq!(size(path), len);
+
+    // two corrupted records before a valid one
+    let i = data.windows(10).position(|w| w == [2; 10]).unwrap();
+    data[i] ^= 0x10;
+    fs::write(path, &data).unwrap();
+    assert!(LogStore::new(path).is_err());
+    assert_eq!(size(path), len);
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn short_header_test() {
+    let path = &path("header");
+    // crash while the header was written
+    fs::write(path, b"MDL").unwrap();
+    let store = LogStore::new(path).unwrap();
+    assert!(store.pull("db", "a", |_| Ok(())).is_err());
+    store.push("db", "a", vec![1]).unwrap();
+    drop(store);
+
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(store.pull("db", "a", |v| Ok(v.to_vec())).unwrap(), vec![1]);
+    let _ = fs::remove_file(path);
+
+    // other short files are not modified
+    fs::write(path, b"abc").unwrap();
+    assert!(LogStore::new(path).is_err());
+    assert_eq!(fs::read(path).unwrap(), b"abc");
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn long_db_test() {
+    let path = &path("long");
+    let store = LogStore::new(path).unwrap();
+    let db: &'static str = Box::leak("d".repeat(70000).into_boxed_str());
+    assert!(store.push(db, "a", vec![1]).is_err());
+
+    // nothing is written
+    store.push("db", "a", vec![1]).unwrap();
+    drop(store);
+    let store = LogStore::new(path).unwrap();
+    assert!(store.pull("db", "a", |_| Ok(())).is_ok());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn compact_test() {
+    let path = &path("compact");
+    let store = LogStore::new(path).unwrap();
+    for round in 0..10 {
+        for i in 0..10 {
+            store.push("db", &format!("k:{}", i), vec![round; 100]).unwrap();
+        }
+    }
+    for i in 5..10 {
+        store.rm("db", &format!("k:{}", i)).unwrap();
+    }
+    assert!(store.garbage() > 0);
+
+    let before = size(path);
+    store.compact().unwrap();
+    assert!(size(path) < before / 10);
+    assert_eq!(store.garbage(), 0);
+    assert_eq!(store.all("db", "k:", |v| Ok(v[0])).unwrap(), vec![9; 5]);
+
+    // writes after the compaction go to the new file
+    store.push("db", "k:9", vec![1]).unwrap();
+    drop(store);
+    let store = LogStore::new(path).unwrap();
+    assert_eq!(store.all("db", "k:", |v| Ok(v[0])).unwrap(), vec![9, 9, 9, 9, 9, 1]);
+    assert_eq!(store.garbage(), 0);
+    let _ = fs::remove_file(path);
+}
@@ -1,17 +1,20 @@
 use mdl::SigType;
+use mdl::Signaler;
 use mdl::SignalerAsync;
+use mdl::Pattern;
 use mdl::Cache;
 use mdl::Model;
+use mdl::Change;
 
 use serde::{Deserialize, Serialize};
 
 use std::fs::remove_dir_all;
 
 use std::sync::{Arc, Mutex};
-use std::{thread, time};
+use std::time;
 
 
-static DB: &'static str = "/tmp/test.lmdb";
+static DB: &str = "/tmp/test.lmdb";
 
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,6 +29,19 @@ impl Model for B {
 }
 
 
+#[derive(Serialize, Deserialize, Debug)]
+struct OtherB {
+    pub id: u32,
+}
+impl Model for OtherB {
+    fn key(&self) -> String {
+        format!("b:{}", self.id)
+    }
+
+    fn db() -> &'static str { "other" }
+}
+
+
 #[test]
 fn basic_signal_test() {
     let db = &format!("{}-basic", DB);
@@ -49,6 +65,8 @@ fn basic_signal_test() {
         *c3.lock().unwrap() += 1;
     }));
 
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+
     let b = B{ id: 1, complex: vec![] };
     let r = b.store_sig(&cache, &sig);
     assert!(r.is_ok());
@@ -62,12 +80,67 @@ fn basic_signal_test() {
 
     let _ = remove_dir_all(db);
 
-    // waiting for signal to come
-    let ten_millis = time::Duration::from_millis(10);
-    thread::sleep(ten_millis);
+    // waiting for signal to come, signals are dispatched in order so all
+    // previous signals are dispatched when "done" comes
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(time::Duration::from_secs(1)).is_some());
 
     assert_eq!(*up_c.lock().unwrap(), 2);
     assert_eq!(*rm_c.lock().unwrap(), 1);
     assert_eq!(*counter.lock().unwrap(), 3);
 }
 
+
+#[test]
+fn typed_signal_test() {
+    let db = &format!("{}-typed", DB);
+    let cache = Cache::new(db).unwrap();
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    let changes = Arc::new(Mutex::new(vec![]));
+    let others = Arc::new(Mutex::new(0));
+
+    let c1 = changes.clone();
+    B::subscribe(&sig, "b", move |change: Change<B>| {
+        let desc = match change {
+            Change::Created(b) => format!("created {}", b.id),
+            Change::Updated { old, new } => {
+                format!("updated {} {} -> {}", new.id, old.complex.len(), new.complex.len())
+            }
+            Change::Deleted(key) => format!("deleted {}", key),
+        };
+        c1.lock().unwrap().push(desc);
+    }).unwrap().detach();
+
+    let c2 = others.clone();
+    OtherB::subscribe(&sig, "b", move |_change| {
+        *c2.lock().unwrap() += 1;
+    }).unwrap().detach();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+
+    let mut b = B{ id: 1, complex: vec![] };
+    b.store_sig(&cache, &sig).unwrap();
+
+    b.complex.push("one".to_string());
+    b.store_sig(&cache, &sig).unwrap();
+
+    b.delete_sig(&cache, &sig).unwrap();
+
+    // same key in other database
+    let o = OtherB{ id: 1 };
+    o.store_sig(&cache, &sig).unwrap();
+
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(time::Duration::from_secs(1)).is_some());
+
+    let _ = remove_dir_all(db);
+
+    assert_eq!(*changes.lock().unwrap(), vec![
+        "created 1".to_string(),
+        "updated 1 0 -> 1".to_string(),
+        "deleted b:1".to_string(),
+    ]);
+    assert_eq!(*others.lock().unwrap(), 1);
+}
@@ -0,0 +1,96 @@
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::Model;
+use mdl::Namespaced;
+use mdl::Store;
+use mdl::ttl;
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct A {
+    pub p1: String,
+    pub p2: u32,
+}
+impl Model for A {
+    fn key(&self) -> String {
+        format!("a:{}", self.p1)
+    }
+}
+
+fn keys<S: Store>(store: &S, prefix: &str) -> Vec<String> {
+    let keys = RefCell::new(vec![]);
+    store.iter_kv("default", prefix, |k, _| {
+        keys.borrow_mut().push(k.to_string());
+        Continue(true)
+    }).unwrap();
+    keys.into_inner()
+}
+
+#[test]
+fn tenants_test() {
+    let path = "/tmp/test-namespaced.lmdb";
+    let _ = fs::remove_dir_all(path);
+    let cache = Arc::new(Cache::new(path).unwrap());
+    let t1 = Namespaced::new(cache.clone(), "t1").unwrap();
+    let t10 = Namespaced::new(cache.clone(), "t10").unwrap();
+    assert_eq!(t1.namespace(), "t1");
+
+    for i in 0..3 {
+        A { p1: format!("{}", i), p2: i }.store(&t1).unwrap();
+        A { p1: format!("{}", i), p2: 10 + i }.store(&t10).unwrap();
+    }
+    A { p1: "other".to_string(), p2: 0 }.store(&t1).unwrap();
+    cache.push("other", "x", vec![1]).unwrap();
+    t1.push("other", "x", vec![2]).unwrap();
+    ttl::expire(&t1, "default", "a:0", Duration::from_secs(60)).unwrap();
+    ttl::expire(&t10, "default", "a:0", Duration::from_secs(60)).unwrap();
+
+    // the models don't see the namespace
+    assert_eq!(A::get(&t1, "a:1").unwrap().p2, 1);
+    assert_eq!(A::get(&t10, "a:1").unwrap().p2, 11);
+    assert_eq!(A::all(&t1, "a:").unwrap().len(), 4);
+    assert_eq!(A::all(&t10, "a:").unwrap().len(), 3);
+    assert_eq!(keys(&t1, "a:"), vec!["a:0", "a:1", "a:2", "a:other"]);
+    assert_eq!(keys(cache.as_ref(), "t10/"), vec!["t10/a:0", "t10/a:1", "t10/a:2"]);
+
+    A::get(&t10, "a:2").unwrap().delete(&t10).unwrap();
+    assert!(A::get(&t10, "a:2").is_err());
+    assert!(A::get(&t1, "a:2").is_ok());
+
+    // drops a tenant, with the expiry times
+    assert_eq!(t1.clear().unwrap(), 5);
+    assert!(A::all(&t1, "a:").unwrap_or_default().is_empty());
+    assert_eq!(A::all(&t10, "a:").unwrap().len(), 2);
+    assert_eq!(cache.pull("other", "x", |v| Ok(v.to_vec())).unwrap(), vec![1]);
+    assert!(ttl::expired(&t1, "default", "").is_empty());
+    assert!(cache.pull(ttl::TTL_DB, "t1/7:default:a:0", |_| Ok(())).is_err());
+    assert!(cache.pull(ttl::TTL_DB, "t10/7:default:a:0", |_| Ok(())).is_ok());
+
+    // the dbs of the namespace are stored
+    let t10 = Namespaced::new(cache.clone(), "t10").unwrap();
+    assert_eq!(t10.clear().unwrap(), 2);
+    assert!(cache.pull(ttl::TTL_DB, "t10/7:default:a:0", |_| Ok(())).is_err());
+
+    // the cleared namespace can be used again
+    A { p1: "1".to_string(), p2: 1 }.store(&t1).unwrap();
+    assert_eq!(t1.clear().unwrap(), 1);
+}
+
+#[test]
+fn namespace_test() {
+    assert!(Namespaced::new(BCache::new().unwrap(), "").is_err());
+    assert!(Namespaced::new(BCache::new().unwrap(), "a/b").is_err());
+
+    let ns = Namespaced::new(BCache::new().unwrap(), "t").unwrap();
+    A { p1: "1".to_string(), p2: 1 }.store(&ns).unwrap();
+    assert_eq!(keys(ns.inner(), ""), vec!["t/a:1"]);
+    assert_eq!(ns.clear().unwrap(), 1);
+    assert!(keys(&ns.into_inner(), "").is_empty());
+}
@@ -0,0 +1,105 @@
+use mdl::Pattern;
+use mdl::Signal;
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SigType;
+
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+#[test]
+fn slow_pooled_callback_test() {
+    let sig = SignalerAsync::with_workers(2).unwrap();
+    sig.start().unwrap();
+
+    // the pooled callback is blocked until the inline one is called
+    let (release, blocked) = channel::<()>();
+    let blocked = Mutex::new(blocked);
+    let _slow = sig.subscribe_pooled("net", Box::new(move |_| {
+        let _ = blocked.lock().unwrap().recv_timeout(Duration::from_secs(5));
+    })).unwrap();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "net").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(2)).is_some());
+    release.send(()).unwrap();
+}
+
+#[test]
+fn pooled_order_test() {
+    let sig = SignalerAsync::with_workers(4).unwrap();
+    sig.start().unwrap();
+    let received = Arc::new(Mutex::new(HashMap::new()));
+
+    let r = received.clone();
+    let _sub = sig.subscribe_pooled("key", Box::new(move |s| {
+        let n = s.new.unwrap()[0];
+        r.lock().unwrap().entry(s.name).or_insert_with(Vec::new).push(n);
+    })).unwrap();
+
+    for n in 0..100 {
+        for key in 0..8 {
+            let mut signal = Signal::new(SigType::Update, &format!("key:{}", key));
+            signal.new = Some(vec![n]);
+            sig.emit_signal(signal).unwrap();
+        }
+    }
+
+    // stop waits for the pooled callbacks
+    sig.stop();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 8);
+    for values in received.values() {
+        assert_eq!(*values, (0..100).collect::<Vec<u8>>());
+    }
+}
+
+#[test]
+fn concurrent_pooled_test() {
+    let sig = SignalerAsync::with_workers(4).unwrap();
+    sig.start().unwrap();
+
+    // (running calls, max running calls at the same time)
+    let state = Arc::new((Mutex::new((0, 0)), Condvar::new()));
+    let s = state.clone();
+    let _sub = sig.subscribe_pooled("key", Box::new(move |_| {
+        let (ref m, ref cvar) = *s;
+        let mut st = m.lock().unwrap();
+        st.0 += 1;
+        st.1 = st.1.max(st.0);
+        cvar.notify_all();
+        // each call waits for another one running at the same time in
+        // other worker, this never happens if the calls are serialized
+        let (mut st, _) = cvar.wait_timeout_while(st, Duration::from_secs(2), |st| st.1 < 2)
+            .unwrap();
+        st.0 -= 1;
+    })).unwrap();
+
+    // different names, assigned to different workers
+    for key in 0..16 {
+        sig.emit(SigType::Update, &format!("key:{}", key)).unwrap();
+    }
+    sig.stop();
+
+    assert!(state.0.lock().unwrap().1 >= 2);
+}
+
+#[test]
+fn without_workers_test() {
+    let sig = SignalerAsync::with_workers(0).unwrap();
+    sig.start().unwrap();
+    let counter = Arc::new(Mutex::new(0));
+
+    let c1 = counter.clone();
+    let _sub = sig.subscribe_pooled("signal", Box::new(move |_| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap();
+
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.stop();
+    assert_eq!(*counter.lock().unwrap(), 1);
+}
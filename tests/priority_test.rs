@@ -0,0 +1,89 @@
+use mdl::Continue;
+use mdl::Pattern;
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SignalerSync;
+use mdl::SigType;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn priority_order_test() {
+    let sig = SignalerSync::new();
+    let calls = Rc::new(RefCell::new(vec![]));
+
+    let c = calls.clone();
+    let _s1 = sig.subscribe("todo:1", Box::new(move |_| c.borrow_mut().push("view"))).unwrap();
+    let c = calls.clone();
+    let _s2 = sig.subscribe("todo", Box::new(move |_| c.borrow_mut().push("log"))).unwrap();
+    let c = calls.clone();
+    let _s3 = sig.subscribe_priority(Pattern::exact("todo:1"), 10, Box::new(move |_| {
+        c.borrow_mut().push("model");
+        Continue(true)
+    })).unwrap();
+    let c = calls.clone();
+    let _s4 = sig.subscribe_priority("t", -1, Box::new(move |_| {
+        c.borrow_mut().push("last");
+        Continue(true)
+    })).unwrap();
+
+    sig.emit(SigType::Update, "todo:1").unwrap();
+    sig.drain();
+
+    // priority first, then subscription order, whatever the pattern is
+    assert_eq!(*calls.borrow(), vec!["model", "view", "log", "last"]);
+}
+
+#[test]
+fn stop_propagation_test() {
+    let sig = SignalerSync::new();
+    let calls = Rc::new(RefCell::new(vec![]));
+
+    let c = calls.clone();
+    let _s1 = sig.subscribe("todo", Box::new(move |s| c.borrow_mut().push(s.name))).unwrap();
+    let c = calls.clone();
+    let _s2 = sig.subscribe_priority("todo", 5, Box::new(move |s| {
+        let stop = s.name == "todo:stop";
+        c.borrow_mut().push(format!("filter {}", s.name));
+        Continue(!stop)
+    })).unwrap();
+
+    sig.emit(SigType::Update, "todo:1").unwrap();
+    sig.emit(SigType::Update, "todo:stop").unwrap();
+    sig.drain();
+
+    assert_eq!(*calls.borrow(), vec![
+        "filter todo:1".to_string(),
+        "todo:1".to_string(),
+        "filter todo:stop".to_string(),
+    ]);
+}
+
+#[test]
+fn async_priority_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let calls = Arc::new(Mutex::new(vec![]));
+
+    for i in 0..10 {
+        let c = calls.clone();
+        sig.subscribe_priority("signal", i, Box::new(move |_| {
+            c.lock().unwrap().push(i);
+            Continue(i > 5)
+        })).unwrap().detach();
+    }
+
+    // a failed callback doesn't stop the propagation
+    sig.subscribe_priority("signal", 8, Box::new(|_| panic!("failed"))).unwrap().detach();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(1)).is_some());
+
+    assert_eq!(*calls.lock().unwrap(), vec![9, 8, 7, 6, 5]);
+    assert_eq!(sig.status().failed, 1);
+}
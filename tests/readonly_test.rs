@@ -0,0 +1,76 @@
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Model;
+use mdl::ReadOnly;
+use mdl::ReadOnlyError;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct A {
+    pub p1: String,
+    pub p2: u32,
+}
+impl Model for A {
+    fn key(&self) -> String {
+        format!("a:{}", self.p1)
+    }
+}
+
+fn check_err(err: anyhow::Error, op: &str, key: &str) {
+    let err = err.downcast_ref::<ReadOnlyError>().unwrap();
+    assert_eq!(err.op, op);
+    assert_eq!(err.key, key);
+}
+
+#[test]
+fn cache_test() {
+    let path = "/tmp/test-readonly.lmdb";
+    let _ = fs::remove_dir_all(path);
+    {
+        let cache = Cache::new(path).unwrap();
+        for i in 0..5 {
+            A { p1: format!("{}", i), p2: i }.store(&cache).unwrap();
+        }
+    }
+    let data = fs::read(format!("{}/data.mdb", path)).unwrap();
+
+    let cache = Cache::read_only(path).unwrap();
+    assert!(cache.is_read_only());
+    assert_eq!(A::get(&cache, "a:3").unwrap().p2, 3);
+    assert_eq!(A::all(&cache, "a:").unwrap().len(), 5);
+    // dbs that don't exist aren't created
+    assert!(cache.pull("other", "a:3", |_| Ok(())).is_err());
+
+    let a = A { p1: "new".to_string(), p2: 10 };
+    check_err(a.store(&cache).unwrap_err(), "push", "a:new");
+    check_err(A::get(&cache, "a:1").unwrap().delete(&cache).unwrap_err(), "rm", "a:1");
+    assert!(A::get(&cache, "a:new").is_err());
+
+    drop(cache);
+    assert_eq!(fs::read(format!("{}/data.mdb", path)).unwrap(), data);
+    assert!(Cache::read_only("/tmp/test-readonly-missing.lmdb").is_err());
+}
+
+#[test]
+fn wrapper_test() {
+    let cache = BCache::new().unwrap();
+    A { p1: "1".to_string(), p2: 1 }.store(&cache).unwrap();
+
+    let ro = ReadOnly::new(cache);
+    assert_eq!(A::get(&ro, "a:1").unwrap().p2, 1);
+    assert_eq!(A::all(&ro, "a:").unwrap().len(), 1);
+
+    let a = A { p1: "2".to_string(), p2: 2 };
+    check_err(a.store(&ro).unwrap_err(), "push", "a:2");
+    check_err(ro.rm("default", "a:1").unwrap_err(), "rm", "a:1");
+    assert_eq!(ro.rm("default", "a:1").unwrap_err().to_string(),
+               "read-only store, rm default:a:1");
+
+    // the inner store can still be written
+    a.store(ro.inner()).unwrap();
+    assert_eq!(A::all(&ro, "a:").unwrap().len(), 2);
+}
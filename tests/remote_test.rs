@@ -0,0 +1,256 @@
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::LogStore;
+use mdl::Model;
+use mdl::Pattern;
+use mdl::ReadOnly;
+use mdl::ReadOnlyError;
+use mdl::RemoteStore;
+use mdl::Server;
+use mdl::SigType;
+use mdl::Signaler;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::fs::{self, remove_dir_all, remove_file};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct B {
+    pub id: u32,
+    pub complex: Vec<String>,
+}
+impl Model for B {
+    fn key(&self) -> String {
+        format!("b:{}", self.id)
+    }
+}
+
+#[test]
+fn remote_store_test() {
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let store = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    for i in 1..10 {
+        B { id: i, complex: vec![] }.store(&store).unwrap();
+    }
+    let mut b = B::get(&store, "b:1").unwrap();
+    b.complex.push("UPDATED".to_string());
+    b.store(&store).unwrap();
+
+    // the objects are in the server store
+    assert_eq!(B::get(server.store(), "b:1").unwrap().complex.len(), 1);
+    assert_eq!(B::all(&store, "b").unwrap().len(), 9);
+
+    B { id: 2, complex: vec![] }.delete(&store).unwrap();
+    let err = B::get(&store, "b:2").err().unwrap();
+    assert!(err.to_string().contains("Not found"));
+    assert!(store.rm("default", "b:2").is_err());
+
+    let n = RefCell::new(0);
+    store.iter("default", "b:", |_| {
+        *n.borrow_mut() += 1;
+        Continue(*n.borrow() < 3)
+    }).unwrap();
+    assert_eq!(*n.borrow(), 3);
+    listener.stop();
+}
+
+#[test]
+fn iter_pages_test() {
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let store = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    for i in 0..2000 {
+        store.push("db", &format!("k:{:05}", i), vec![]).unwrap();
+    }
+    let keys = RefCell::new(vec![]);
+    store.iter_kv("db", "k:", |k, _| {
+        keys.borrow_mut().push(k.to_string());
+        Continue(true)
+    }).unwrap();
+    let expected: Vec<String> = (0..2000).map(|i| format!("k:{:05}", i)).collect();
+    assert_eq!(keys.into_inner(), expected);
+}
+
+/// Keys after `after` with the prefix
+fn after<S: Store>(store: &S, prefix: &str, after: &str) -> Vec<String> {
+    let keys = RefCell::new(vec![]);
+    store.iter_kv_after("db", prefix, after, |k, _| {
+        keys.borrow_mut().push(k.to_string());
+        Continue(true)
+    }).unwrap();
+    keys.into_inner()
+}
+
+fn iter_after<S: Store>(store: &S) {
+    for k in ["a:1", "k:1", "k:2", "k:3", "z:1"] {
+        store.push("db", k, vec![]).unwrap();
+    }
+    assert_eq!(after(store, "k:", "k:1"), vec!["k:2", "k:3"]);
+    assert_eq!(after(store, "k:", "k:15"), vec!["k:2", "k:3"]);
+    assert_eq!(after(store, "k:", "a:1"), vec!["k:1", "k:2", "k:3"]);
+    assert!(after(store, "k:", "k:3").is_empty());
+    assert!(after(store, "k:", "x").is_empty());
+    assert_eq!(after(store, "", "k:3"), vec!["z:1"]);
+}
+
+#[test]
+fn iter_after_test() {
+    iter_after(&BCache::new().unwrap());
+
+    let db = "/tmp/test.lmdb-after";
+    let _ = remove_dir_all(db);
+    iter_after(&Cache::new(db).unwrap());
+    let _ = remove_dir_all(db);
+
+    let log = "/tmp/test-after.log";
+    let _ = remove_file(log);
+    iter_after(&LogStore::new(log).unwrap());
+    let _ = remove_file(log);
+
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    iter_after(&RemoteStore::connect_tcp(listener.addr()).unwrap());
+}
+
+#[test]
+fn cache_pages_test() {
+    let db = "/tmp/test.lmdb-pages";
+    let _ = remove_dir_all(db);
+    let server = Server::new(Cache::new(db).unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let store = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    for i in 0..2000 {
+        store.push("db", &format!("k:{:05}", i), vec![]).unwrap();
+    }
+    store.push("db", "z", vec![]).unwrap();
+    let n = RefCell::new(0);
+    store.iter_kv("db", "k:", |_, _| {
+        *n.borrow_mut() += 1;
+        Continue(true)
+    }).unwrap();
+    assert_eq!(n.into_inner(), 2000);
+
+    listener.stop();
+    let _ = remove_dir_all(db);
+}
+
+#[test]
+fn error_codes_test() {
+    let server = Server::new(ReadOnly::new(BCache::new().unwrap())).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let store = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    // the errors can be checked like the local store errors
+    let err = store.pull("db", "a", |_| Ok(())).err().unwrap();
+    assert!(matches!(err.downcast_ref::<lmdb::Error>(), Some(lmdb::Error::NotFound)));
+    assert!(err.to_string().contains("Not found"));
+
+    let err = store.push("db", "a", vec![1]).err().unwrap();
+    let expected = ReadOnlyError { op: "push", db: "db".to_string(), key: "a".to_string() };
+    assert_eq!(err.downcast_ref::<ReadOnlyError>(), Some(&expected));
+    listener.stop();
+}
+
+#[test]
+fn big_values_test() {
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let store = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    // the pages are split by size
+    for i in 0..12 {
+        store.push("db", &format!("k:{}", i), vec![i; 512 * 1024]).unwrap();
+    }
+    let n = RefCell::new(0);
+    store.iter_kv("db", "k:", |_, v| {
+        assert_eq!(v.len(), 512 * 1024);
+        *n.borrow_mut() += 1;
+        Continue(true)
+    }).unwrap();
+    assert_eq!(n.into_inner(), 12);
+
+    // too big for a frame, the connection is still usable
+    assert!(store.push("db", "big", vec![0; 17 * 1024 * 1024]).is_err());
+    server.store().push("db", "big", vec![0; 17 * 1024 * 1024]).unwrap();
+    assert!(store.pull("db", "big", |_| Ok(())).is_err());
+    assert!(store.pull("db", "k:1", |_| Ok(())).is_ok());
+
+    // a huge frame length closes the connection
+    let mut raw = TcpStream::connect(listener.addr()).unwrap();
+    raw.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    assert_eq!(raw.read(&mut [0; 16]).unwrap(), 0);
+    listener.stop();
+}
+
+#[test]
+fn remote_signals_test() {
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_tcp("127.0.0.1:0").unwrap();
+    let a = RemoteStore::connect_tcp(listener.addr()).unwrap();
+    let b = RemoteStore::connect_tcp(listener.addr()).unwrap();
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let r = received.clone();
+    let store = a.clone();
+    let sub = a.subscribe(Pattern::glob("b:*"), Box::new(move |s| {
+        // the callbacks can use the store
+        let b = B::get(&store, &s.name).ok();
+        r.lock().unwrap().push((s.name, b.map(|b| b.id)));
+    })).unwrap();
+    // the subscription is sent before the next request
+    let waiter = a.waiter(Pattern::exact("b:1")).unwrap();
+
+    B { id: 1, complex: vec![] }.store_sig(&b, &b).unwrap();
+    b.emit(SigType::Update, "other").unwrap();
+    assert!(waiter.wait(Duration::from_secs(5)).is_some());
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(*received.lock().unwrap(), vec![("b:1".to_string(), Some(1))]);
+
+    // the server signaler sends signals to the clients
+    let waiter = a.waiter("server").unwrap();
+    server.signaler().emit(SigType::Update, "server").unwrap();
+    assert!(waiter.wait(Duration::from_secs(5)).is_some());
+
+    drop(sub);
+    let waiter = a.waiter("b:2").unwrap();
+    B { id: 2, complex: vec![] }.store_sig(&b, &b).unwrap();
+    assert!(waiter.wait(Duration::from_secs(5)).is_some());
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket_test() {
+    let path = "/tmp/test-mdl-remote.sock";
+    let server = Server::new(BCache::new().unwrap()).unwrap();
+    let listener = server.listen_unix(path).unwrap();
+    let store = RemoteStore::connect_unix(path).unwrap();
+
+    B { id: 1, complex: vec![] }.store(&store).unwrap();
+    assert_eq!(B::get(&store, "b:1").unwrap().id, 1);
+
+    // the clients get an error when the server stops
+    listener.stop();
+    thread::sleep(Duration::from_millis(100));
+    assert!(!store.is_connected());
+    assert!(B::get(&store, "b:1").is_err());
+    assert!(std::fs::metadata(path).is_err());
+
+    // other files are not replaced
+    fs::write(path, "data").unwrap();
+    assert!(server.listen_unix(path).is_err());
+    assert_eq!(fs::read(path).unwrap(), b"data");
+    let _ = remove_file(path);
+}
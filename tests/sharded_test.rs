@@ -0,0 +1,170 @@
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::Model;
+use mdl::Partition;
+use mdl::ShardedStore;
+use mdl::Store;
+use mdl::sharded::reshard;
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct B {
+    pub id: u32,
+    pub name: String,
+}
+impl Model for B {
+    fn key(&self) -> String {
+        format!("b:{:03}", self.id)
+    }
+}
+
+fn path(name: &str) -> String {
+    let path = format!("/tmp/test-sharded-{}", name);
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+fn ids<S: Store>(store: &S, prefix: &str) -> Vec<u32> {
+    B::all(store, prefix).unwrap().iter().map(|b| b.id).collect()
+}
+
+#[test]
+fn hash_test() {
+    let store = ShardedStore::open(&path("hash"), 4, Partition::Hash).unwrap();
+    for i in (0..50).rev() {
+        B { id: i, name: format!("b{}", i) }.store(&store).unwrap();
+    }
+
+    // all the shards are used
+    for shard in store.shards() {
+        assert!(!B::all(shard, "b:").unwrap().is_empty());
+    }
+
+    assert_eq!(B::get(&store, "b:007").unwrap().name, "b7");
+    assert_eq!(ids(&store, "b:"), (0..50).collect::<Vec<_>>());
+    assert_eq!(ids(&store, "b:01"), (10..20).collect::<Vec<_>>());
+
+    B::get(&store, "b:007").unwrap().delete(&store).unwrap();
+    assert!(B::get(&store, "b:007").is_err());
+    assert_eq!(ids(&store, "b:00").len(), 9);
+    assert!(B::all(&store, "c:").is_err());
+}
+
+#[test]
+fn merge_batches_test() {
+    // more keys than a merge batch in each shard
+    let shards = (0..3).map(|_| BCache::new().unwrap()).collect();
+    let store = ShardedStore::new(shards, Partition::Hash).unwrap();
+    for i in 0..2000 {
+        store.push("db", &format!("k:{:05}", i), vec![]).unwrap();
+    }
+
+    let keys = RefCell::new(vec![]);
+    store.iter_kv("db", "k:", |k, _| {
+        keys.borrow_mut().push(k.to_string());
+        Continue(true)
+    }).unwrap();
+    let expected: Vec<String> = (0..2000).map(|i| format!("k:{:05}", i)).collect();
+    assert_eq!(keys.into_inner(), expected);
+
+    let n = RefCell::new(0);
+    store.iter_kv("db", "k:019", |_, _| {
+        *n.borrow_mut() += 1;
+        Continue(*n.borrow() < 500)
+    }).unwrap();
+    assert_eq!(n.into_inner(), 100);
+}
+
+#[test]
+fn range_test() {
+    let splits = vec!["b:010".to_string(), "b:020".to_string()];
+    let store = ShardedStore::open(&path("range"), 3, Partition::Range(splits)).unwrap();
+    for i in 0..30 {
+        B { id: i, name: format!("b{}", i) }.store(&store).unwrap();
+    }
+
+    assert_eq!(store.shard("default", "b:009"), 0);
+    assert_eq!(store.shard("default", "b:010"), 1);
+    assert_eq!(store.shard("default", "b:025"), 2);
+    assert_eq!(ids(&store.shards()[1], "b:"), (10..20).collect::<Vec<_>>());
+
+    assert_eq!(ids(&store, "b:"), (0..30).collect::<Vec<_>>());
+    assert_eq!(ids(&store, "b:01"), (10..20).collect::<Vec<_>>());
+    assert_eq!(ids(&store, "b:02"), (20..30).collect::<Vec<_>>());
+}
+
+#[test]
+fn layout_test() {
+    assert!(ShardedStore::<Cache>::new(vec![], Partition::Hash).is_err());
+    let splits = vec!["b".to_string()];
+    assert!(ShardedStore::open(&path("badrange"), 3, Partition::Range(splits)).is_err());
+
+    let path = path("layout");
+    drop(ShardedStore::open(&path, 2, Partition::Hash).unwrap());
+    assert!(ShardedStore::open(&path, 3, Partition::Hash).is_err());
+}
+
+#[test]
+fn reshard_test() {
+    let path = path("reshard");
+    {
+        let store = ShardedStore::open(&path, 2, Partition::Hash).unwrap();
+        for i in 0..40 {
+            B { id: i, name: format!("b{}", i) }.store(&store).unwrap();
+        }
+        // only in one shard
+        store.push("other", "x", vec![1]).unwrap();
+    }
+
+    assert_eq!(reshard(&path, 5, Partition::Hash).unwrap(), 41);
+
+    let store = ShardedStore::open(&path, 5, Partition::Hash).unwrap();
+    assert_eq!(ids(&store, "b:"), (0..40).collect::<Vec<_>>());
+    assert_eq!(store.pull("other", "x", |v| Ok(v.to_vec())).unwrap(), vec![1]);
+    for (i, shard) in store.shards().iter().enumerate() {
+        for b in B::all(shard, "b:").unwrap() {
+            assert_eq!(store.shard("default", &b.key()), i);
+        }
+    }
+    drop(store);
+    assert!(fs::metadata(format!("{}/g0", path)).is_err());
+    assert!(ShardedStore::open(&path, 2, Partition::Hash).is_err());
+}
+
+#[test]
+fn interrupted_reshard_test() {
+    let path = path("interrupted");
+    {
+        let store = ShardedStore::open(&path, 2, Partition::Hash).unwrap();
+        for i in 0..10 {
+            B { id: i, name: format!("b{}", i) }.store(&store).unwrap();
+        }
+    }
+
+    // the new shards of a reshard that didn't finish are not used
+    fs::create_dir_all(format!("{}/g1/shard-0", path)).unwrap();
+    fs::write(format!("{}/g1/shard-0/data.mdb", path), b"partial").unwrap();
+    {
+        let store = ShardedStore::open(&path, 2, Partition::Hash).unwrap();
+        assert_eq!(ids(&store, "b:"), (0..10).collect::<Vec<_>>());
+    }
+
+    assert_eq!(reshard(&path, 3, Partition::Hash).unwrap(), 10);
+    let store = ShardedStore::open(&path, 3, Partition::Hash).unwrap();
+    assert_eq!(ids(&store, "b:"), (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn invalid_layout_test() {
+    let path = path("invalid");
+    drop(ShardedStore::open(&path, 2, Partition::Hash).unwrap());
+    fs::write(format!("{}/mdl-shards", path), b"x").unwrap();
+    assert!(ShardedStore::open(&path, 2, Partition::Hash).is_err());
+    assert!(reshard(&path, 3, Partition::Hash).is_err());
+    assert!(reshard("/tmp/test-sharded-missing", 3, Partition::Hash).is_err());
+}
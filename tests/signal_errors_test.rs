@@ -0,0 +1,127 @@
+use mdl::Pattern;
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SignalerSync;
+use mdl::SigType;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn panic_isolation_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let counter = Arc::new(Mutex::new(0));
+    let errors = Arc::new(Mutex::new(vec![]));
+
+    let e = errors.clone();
+    sig.on_error(Box::new(move |err| {
+        e.lock().unwrap().push((err.id, err.signals[0].name.clone(), err.message.clone()));
+    }));
+
+    let bad = sig.subscribe("signal", Box::new(|sig| {
+        if sig.name == "signal:bad" {
+            panic!("bad signal");
+        }
+    })).unwrap().detach();
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap().detach();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "signal:bad").unwrap();
+    sig.emit(SigType::Update, "signal:good").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(1)).is_some());
+
+    // the loop is still running and the callbacks can be modified
+    assert_eq!(*counter.lock().unwrap(), 2);
+    sig.unsubscribe(bad);
+    let id = sig.subscribe("other", Box::new(|_| {})).unwrap().detach();
+    sig.unsubscribe(id);
+
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0], (bad, "signal:bad".to_string(), "bad signal".to_string()));
+
+    let status = sig.status();
+    assert!(status.running);
+    assert_eq!(status.failed, 1);
+    assert_eq!(status.subscriptions, 2);
+    assert_eq!(status.pending, 0);
+    assert_eq!(status.last_error.unwrap().id, bad);
+}
+
+#[test]
+fn panic_in_error_hook_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    sig.on_error(Box::new(|_err| panic!("error hook")));
+    sig.subscribe("signal", Box::new(|_sig| panic!("callback"))).unwrap().detach();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(1)).is_some());
+
+    let status = sig.status();
+    assert!(status.running);
+    assert_eq!(status.failed, 1);
+}
+
+#[test]
+fn replace_error_hook_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let errors = Arc::new(Mutex::new(vec![]));
+
+    // the first error replaces the hook
+    let s = sig.clone();
+    let e = errors.clone();
+    sig.on_error(Box::new(move |_err| {
+        e.lock().unwrap().push("first");
+        let e = e.clone();
+        s.on_error(Box::new(move |_err| e.lock().unwrap().push("second")));
+    }));
+    sig.subscribe("signal", Box::new(|_sig| panic!("callback"))).unwrap().detach();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.emit(SigType::Update, "signal").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(1)).is_some());
+
+    assert_eq!(*errors.lock().unwrap(), vec!["first", "second"]);
+    assert_eq!(sig.status().failed, 2);
+}
+
+#[test]
+fn sync_panic_isolation_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    sig.subscribe_batch("row", Duration::from_millis(1), Box::new(|_sigs| {
+        panic!("batch");
+    })).unwrap().detach();
+    let c1 = counter.clone();
+    sig.subscribe("row", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "row:1").unwrap();
+    sig.emit(SigType::Update, "row:2").unwrap();
+    assert_eq!(sig.status().pending, 2);
+    assert_eq!(sig.drain(), 2);
+    assert!(sig.wait_one(Duration::from_millis(20)).is_none());
+
+    assert_eq!(*counter.borrow(), 2);
+    let status = sig.status();
+    assert_eq!(status.delivered, 2);
+    assert_eq!(status.failed, 1);
+    assert_eq!(status.last_error.unwrap().signals.len(), 2);
+}
@@ -0,0 +1,67 @@
+#![cfg(unix)]
+
+use mdl::Signaler;
+use mdl::SignalerSync;
+use mdl::SigType;
+
+use std::cell::RefCell;
+use std::io::Read;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+
+/// Reads the fd without closing it, returns true if it was readable
+fn readable(fd: RawFd) -> bool {
+    let mut s = ManuallyDrop::new(unsafe { UnixStream::from_raw_fd(fd) });
+    let mut buf = [0; 64];
+    matches!(s.read(&mut buf), Ok(n) if n > 0)
+}
+
+#[test]
+fn fd_readable_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    let _sub = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap();
+
+    let fd = sig.fd().unwrap();
+    assert_eq!(sig.fd().unwrap(), fd);
+    assert!(!readable(fd));
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    assert!(readable(fd));
+
+    // the fd is readable again while there are pending signals
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    assert!(sig.signal_loop_sync());
+    assert!(sig.signal_loop_sync());
+    assert!(sig.signal_loop_sync());
+    assert!(!readable(fd));
+    assert_eq!(*counter.borrow(), 2);
+
+    sig.emit(SigType::Update, "signal:3").unwrap();
+    assert_eq!(sig.drain(), 1);
+    assert!(!readable(fd));
+}
+
+#[test]
+fn fd_pending_test() {
+    let sig = SignalerSync::new();
+
+    // signals emitted before asking for the fd
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    let fd = sig.fd().unwrap();
+    assert!(readable(fd));
+
+    // pending after clearing the notifications
+    assert!(sig.signal_loop_sync());
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    sig.emit(SigType::Update, "signal:3").unwrap();
+    assert!(readable(fd));
+    assert_eq!(sig.drain(), 2);
+    assert!(!readable(fd));
+}
@@ -0,0 +1,128 @@
+use mdl::Signaler;
+use mdl::SignalerSync;
+use mdl::SigType;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[test]
+fn wait_one_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap().detach();
+
+    assert!(sig.wait_one(Duration::from_millis(1)).is_none());
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    let s = sig.wait_one(Duration::from_secs(1)).unwrap();
+    assert_eq!(s.name, "signal:1");
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn drain_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.emit(SigType::Update, "other").unwrap();
+    sig.emit(SigType::Delete, "signal:2").unwrap();
+
+    assert_eq!(sig.drain(), 3);
+    assert_eq!(*counter.borrow(), 2);
+    assert_eq!(sig.drain(), 0);
+}
+
+#[test]
+fn run_until_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    sig.emit(SigType::Update, "quit").unwrap();
+    sig.emit(SigType::Update, "signal:3").unwrap();
+
+    assert!(sig.run_until(|s| s.name == "quit"));
+    assert_eq!(*counter.borrow(), 2);
+    assert_eq!(sig.drain(), 1);
+    assert_eq!(*counter.borrow(), 3);
+}
+
+#[test]
+fn wait_for_test() {
+    let sig = SignalerSync::new();
+
+    sig.emit(SigType::Update, "other").unwrap();
+    sig.emit(SigType::Delete, "signal:1").unwrap();
+
+    let s = sig.wait_for("signal", Duration::from_secs(1)).unwrap();
+    assert_eq!(s.name, "signal:1");
+
+    assert!(sig.wait_for("signal", Duration::from_millis(1)).is_none());
+}
+
+#[test]
+fn coalesced_test() {
+    let sig = SignalerSync::new();
+    let received = Rc::new(RefCell::new(vec![]));
+
+    let r = received.clone();
+    sig.subscribe_coalesced("row", Duration::from_millis(50), Box::new(move |s| {
+        r.borrow_mut().push((s.name, s.type_));
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "row:1").unwrap();
+    sig.emit(SigType::Update, "row:2").unwrap();
+    sig.emit(SigType::Update, "row:1").unwrap();
+    sig.emit(SigType::Delete, "row:1").unwrap();
+
+    // signals are dispatched but the window is still open
+    assert_eq!(sig.drain(), 4);
+    assert!(received.borrow().is_empty());
+
+    // the window ends while waiting
+    assert!(sig.wait_one(Duration::from_millis(200)).is_none());
+    let received = received.borrow();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].0, "row:1");
+    assert!(matches!(received[0].1, SigType::Delete));
+    assert_eq!(received[1].0, "row:2");
+}
+
+#[test]
+fn batch_test() {
+    let sig = SignalerSync::new();
+    let batches = Rc::new(RefCell::new(vec![]));
+
+    let b = batches.clone();
+    sig.subscribe_batch("row", Duration::from_millis(50), Box::new(move |sigs| {
+        b.borrow_mut().push(sigs.len());
+    })).unwrap().detach();
+
+    for i in 0..100 {
+        sig.emit(SigType::Update, &format!("row:{}", i)).unwrap();
+    }
+    sig.emit(SigType::Update, "other").unwrap();
+
+    assert_eq!(sig.drain(), 101);
+    assert!(batches.borrow().is_empty());
+
+    assert!(sig.wait_one(Duration::from_millis(200)).is_none());
+    assert_eq!(*batches.borrow(), vec![100]);
+}
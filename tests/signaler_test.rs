@@ -1,6 +1,7 @@
 use mdl::Signaler;
 use mdl::SignalerAsync;
 use mdl::SigType;
+use mdl::Pattern;
 
 use std::sync::{Arc, Mutex};
 use std::{thread, time};
@@ -16,9 +17,9 @@ fn one_signal_test() {
     let c1 = counter.clone();
     let t1: thread::JoinHandle<_> =
     thread::spawn(move || {
-        let _ = sig1.subscribe("signal", Box::new(move |_sig| {
+        sig1.subscribe("signal", Box::new(move |_sig| {
             *c1.lock().unwrap() += 1;
-        }));
+        })).unwrap().detach();
     });
 
     // waiting for threads to finish
@@ -36,8 +37,8 @@ fn one_signal_test() {
     // waiting for threads to finish
     t2.join().unwrap();
 
-    let ten_millis = time::Duration::from_millis(10);
-    thread::sleep(ten_millis);
+    // dispatches the emitted signals before returning
+    sig.stop();
 
     assert_eq!(*counter.lock().unwrap(), 3);
 }
@@ -55,13 +56,13 @@ fn two_signal_test() {
     let c2 = counter2.clone();
     let t1: thread::JoinHandle<_> =
     thread::spawn(move || {
-        let _ = sig1.subscribe("signal", Box::new(move |_sig| {
+        sig1.subscribe("signal", Box::new(move |_sig| {
             *c1.lock().unwrap() += 1;
-        }));
+        })).unwrap().detach();
 
-        let _ = sig1.subscribe("others", Box::new(move |_sig| {
+        sig1.subscribe("others", Box::new(move |_sig| {
             *c2.lock().unwrap() += 1;
-        }));
+        })).unwrap().detach();
     });
 
     // waiting for threads to finish
@@ -80,8 +81,8 @@ fn two_signal_test() {
     // waiting for threads to finish
     t2.join().unwrap();
 
-    let ten_millis = time::Duration::from_millis(10);
-    thread::sleep(ten_millis);
+    // dispatches the emitted signals before returning
+    sig.stop();
 
     assert_eq!(*counter.lock().unwrap(), 3);
     assert_eq!(*counter2.lock().unwrap(), 1);
@@ -100,10 +101,10 @@ fn unsubscribe_test() {
     let t1: thread::JoinHandle<_> =
     thread::spawn(move || {
         let sig2 = sig1.clone();
-        let _ = sig1.subscribe("unsub", Box::new(move |_sig| {
+        sig1.subscribe("unsub", Box::new(move |_sig| {
             *c1.lock().unwrap() += 1;
             sig2.unsubscribe(1);
-        }));
+        })).unwrap().detach();
     });
 
     // waiting for threads to finish
@@ -121,8 +122,138 @@ fn unsubscribe_test() {
     // waiting for threads to finish
     t2.join().unwrap();
 
-    let ten_millis = time::Duration::from_millis(10);
-    thread::sleep(ten_millis);
+    // dispatches the emitted signals before returning
+    sig.stop();
 
     assert_eq!(*counter.lock().unwrap(), 1);
 }
+
+#[test]
+fn pattern_match_test() {
+    assert!(Pattern::prefix("todo").matches("todo:1"));
+    assert!(!Pattern::prefix("todo").matches("tod"));
+
+    assert!(Pattern::exact("todo:1").matches("todo:1"));
+    assert!(!Pattern::exact("todo:1").matches("todo:10"));
+
+    let g = Pattern::glob("todo:*:done");
+    assert!(g.matches("todo:1:done"));
+    assert!(g.matches("todo:a:b:done"));
+    assert!(g.matches("todo::done"));
+    assert!(!g.matches("todo:1:undone:2"));
+    assert!(!g.matches("other:1:done"));
+    assert!(Pattern::glob("t?do").matches("tödo"));
+
+    let r = Pattern::regex(r"^todo:[0-9]+$").unwrap();
+    assert!(r.matches("todo:42"));
+    assert!(!r.matches("todo:a"));
+    assert!(Pattern::regex("(").is_err());
+}
+
+#[test]
+fn pattern_signal_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let prefix = Arc::new(Mutex::new(0));
+    let exact = Arc::new(Mutex::new(0));
+    let glob = Arc::new(Mutex::new(0));
+    let regex = Arc::new(Mutex::new(0));
+
+    let c = prefix.clone();
+    sig.subscribe("todo", Box::new(move |_sig| {
+        *c.lock().unwrap() += 1;
+    })).unwrap().detach();
+    let c = exact.clone();
+    sig.subscribe(Pattern::exact("todo:1"), Box::new(move |_sig| {
+        *c.lock().unwrap() += 1;
+    })).unwrap().detach();
+    let c = glob.clone();
+    sig.subscribe(Pattern::glob("todo:*:done"), Box::new(move |_sig| {
+        *c.lock().unwrap() += 1;
+    })).unwrap().detach();
+    let c = regex.clone();
+    sig.subscribe(Pattern::regex(r"[0-9]:done$").unwrap(), Box::new(move |_sig| {
+        *c.lock().unwrap() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "todo:1").unwrap();
+    sig.emit(SigType::Update, "todo:1:done").unwrap();
+    sig.emit(SigType::Update, "todo:a:done").unwrap();
+    sig.emit(SigType::Update, "other:2:done").unwrap();
+
+    // dispatches the emitted signals before returning
+    sig.stop();
+
+    assert_eq!(*prefix.lock().unwrap(), 3);
+    assert_eq!(*exact.lock().unwrap(), 1);
+    assert_eq!(*glob.lock().unwrap(), 2);
+    assert_eq!(*regex.lock().unwrap(), 2);
+}
+
+#[test]
+fn clear_signal_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let counter = Arc::new(Mutex::new(0));
+
+    let c1 = counter.clone();
+    sig.subscribe("clear", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap().detach();
+    let c2 = counter.clone();
+    sig.subscribe(Pattern::exact("clear"), Box::new(move |_sig| {
+        *c2.lock().unwrap() += 10;
+    })).unwrap().detach();
+
+    // only the prefix subscription is removed
+    sig.clear_signal("clear");
+    sig.emit(SigType::Update, "clear").unwrap();
+
+    // dispatches the emitted signals before returning
+    sig.stop();
+
+    assert_eq!(*counter.lock().unwrap(), 10);
+}
+
+#[test]
+fn waiter_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    let waiter = sig.waiter("wait").unwrap();
+    let sig2 = sig.clone();
+    thread::spawn(move || {
+        sig2.emit(SigType::Update, "other").unwrap();
+        sig2.emit(SigType::Delete, "wait:1").unwrap();
+    });
+
+    let s = waiter.wait(time::Duration::from_secs(1)).unwrap();
+    assert_eq!(s.name, "wait:1");
+    assert!(waiter.wait(time::Duration::from_millis(1)).is_none());
+
+    assert!(sig.wait_for("wait", time::Duration::from_millis(1)).is_none());
+}
+
+#[test]
+fn batch_signal_test() {
+    use std::sync::mpsc::channel;
+
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    let (tx, rx) = channel();
+    let tx = Mutex::new(tx);
+    sig.subscribe_batch("row", time::Duration::from_millis(50), Box::new(move |sigs| {
+        let names: Vec<String> = sigs.into_iter().map(|s| s.name).collect();
+        let _ = tx.lock().unwrap().send(names);
+    })).unwrap().detach();
+
+    for i in 0..100 {
+        sig.emit(SigType::Update, &format!("row:{}", i)).unwrap();
+    }
+
+    let names = rx.recv_timeout(time::Duration::from_secs(1)).unwrap();
+    assert_eq!(names.len(), 100);
+    assert_eq!(names[0], "row:0");
+    assert_eq!(names[99], "row:99");
+}
@@ -0,0 +1,137 @@
+use mdl::BCache;
+use mdl::Model;
+use mdl::Store;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct A {
+    pub p1: String,
+    pub p2: u32,
+}
+impl Model for A {
+    fn key(&self) -> String {
+        format!("a:{}", self.p1)
+    }
+}
+
+fn path(name: &str) -> String {
+    let path = format!("/tmp/test-{}.snapshot", name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Temporary files of the snapshot file name left in /tmp
+fn tmp_files(name: &str) -> usize {
+    fs::read_dir("/tmp").unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let n = e.file_name().to_string_lossy().to_string();
+            n.starts_with(&format!(".{}.", name)) && n.ends_with(".tmp")
+        })
+        .count()
+}
+
+#[test]
+fn save_load_test() {
+    let path = &path("save");
+    let cache = BCache::new().unwrap();
+    for i in 0..10 {
+        A { p1: format!("a{}", i), p2: i }.store(&cache).unwrap();
+    }
+    cache.push("other", "key", vec![1, 2, 3]).unwrap();
+    cache.save_to(path).unwrap();
+    // the temporary file is renamed
+    assert_eq!(tmp_files("test-save.snapshot"), 0);
+
+    let loaded = BCache::load_from(path).unwrap();
+    assert_eq!(A::all(&loaded, "a").unwrap().len(), 10);
+    assert_eq!(A::get(&loaded, "a:a3").unwrap().p2, 3);
+    assert_eq!(loaded.pull("other", "key", |d| Ok(d.to_vec())).unwrap(), vec![1, 2, 3]);
+
+    // saving again replaces the file
+    loaded.rm("other", "key").unwrap();
+    loaded.save_to(path).unwrap();
+    let loaded = BCache::load_from(path).unwrap();
+    assert!(loaded.pull("other", "key", |_| Ok(())).is_err());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn concurrent_save_test() {
+    let path = &path("concurrent");
+    let cache = BCache::new().unwrap();
+    for i in 0..100 {
+        A { p1: format!("a{}", i), p2: i }.store(&cache).unwrap();
+    }
+
+    let threads: Vec<_> = (0..8).map(|_| {
+        let (cache, path) = (cache.clone(), path.clone());
+        thread::spawn(move || {
+            for _ in 0..5 {
+                cache.save_to(&path).unwrap();
+            }
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(A::all(&BCache::load_from(path).unwrap(), "a").unwrap().len(), 100);
+    assert_eq!(tmp_files("test-concurrent.snapshot"), 0);
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn corrupted_test() {
+    let path = &path("corrupted");
+    assert!(BCache::load_from(path).is_err());
+
+    let cache = BCache::new().unwrap();
+    cache.push("db", "key", vec![0; 100]).unwrap();
+    cache.save_to(path).unwrap();
+
+    let mut data = fs::read(path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(path, &data).unwrap();
+    let err = BCache::load_from(path).err().unwrap();
+    assert!(err.to_string().contains("corrupted"));
+
+    // truncated
+    fs::write(path, &data[..50]).unwrap();
+    assert!(BCache::load_from(path).is_err());
+
+    // other version
+    data[4] = 99;
+    fs::write(path, &data).unwrap();
+    let err = BCache::load_from(path).err().unwrap();
+    assert!(err.to_string().contains("version"));
+
+    fs::write(path, b"not a snapshot").unwrap();
+    assert!(BCache::load_from(path).is_err());
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn autosave_test() {
+    let path = &path("autosave");
+    let cache = BCache::new().unwrap();
+    let autosave = cache.autosave(path, Duration::from_millis(20)).unwrap();
+
+    cache.push("db", "a", vec![1]).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let loaded = BCache::load_from(path).unwrap();
+    assert!(loaded.pull("db", "a", |_| Ok(())).is_ok());
+
+    // stop saves the last changes
+    cache.push("db", "b", vec![2]).unwrap();
+    autosave.stop();
+    let loaded = BCache::load_from(path).unwrap();
+    assert!(loaded.pull("db", "b", |_| Ok(())).is_ok());
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,162 @@
+use mdl::Signaler;
+use mdl::SignalerAsync;
+use mdl::SignalerSync;
+use mdl::SigType;
+use mdl::Pattern;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn drop_unsubscribe_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    let sub = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap();
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.drain();
+    assert_eq!(*counter.borrow(), 1);
+
+    drop(sub);
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    sig.drain();
+    assert_eq!(*counter.borrow(), 1);
+    assert_eq!(sig.status().subscriptions, 0);
+}
+
+#[test]
+fn detach_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    let id = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap().detach();
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.drain();
+    assert_eq!(*counter.borrow(), 1);
+
+    sig.unsubscribe(id);
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    sig.drain();
+    assert_eq!(*counter.borrow(), 1);
+}
+
+#[test]
+fn subscribe_once_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let c1 = counter.clone();
+    let _sub = sig.subscribe_once("signal", Box::new(move |_sig| {
+        *c1.borrow_mut() += 1;
+    })).unwrap();
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.emit(SigType::Update, "signal:2").unwrap();
+    sig.drain();
+
+    assert_eq!(*counter.borrow(), 1);
+    assert_eq!(sig.status().subscriptions, 0);
+}
+
+#[test]
+fn drop_in_other_thread_test() {
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+    let counter = Arc::new(Mutex::new(0));
+
+    let c1 = counter.clone();
+    let sub = sig.subscribe("signal", Box::new(move |_sig| {
+        *c1.lock().unwrap() += 1;
+    })).unwrap();
+
+    thread::spawn(move || drop(sub)).join().unwrap();
+
+    let done = sig.waiter(Pattern::exact("done")).unwrap();
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.emit(SigType::Update, "done").unwrap();
+    assert!(done.wait(Duration::from_secs(1)).is_some());
+    drop(done);
+    assert_eq!(*counter.lock().unwrap(), 0);
+    assert_eq!(sig.status().subscriptions, 0);
+}
+
+#[test]
+fn many_subscriptions_test() {
+    let sig = SignalerSync::new();
+    let counter = Rc::new(RefCell::new(0));
+
+    let mut subs = vec![];
+    for i in 0..1000 {
+        let c1 = counter.clone();
+        let sub = sig.subscribe(format!("signal:{}", i % 10), Box::new(move |_sig| {
+            *c1.borrow_mut() += 1;
+        })).unwrap();
+        subs.push(sub);
+    }
+    assert_eq!(sig.status().subscriptions, 1000);
+
+    // removing half of the subscriptions
+    let ids: Vec<u32> = subs.iter().map(|s| s.id()).collect();
+    for id in ids.iter().step_by(2) {
+        sig.unsubscribe(*id);
+    }
+    subs.truncate(500);
+    assert_eq!(sig.status().subscriptions, 250);
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.drain();
+    // only the odd subscriptions under 500 are alive
+    assert_eq!(*counter.borrow(), 50);
+}
+
+#[test]
+fn concurrent_subscribe_test() {
+    let sig = SignalerAsync::new();
+    sig.start().unwrap();
+    let counter = Arc::new(Mutex::new(0));
+
+    let threads: Vec<_> = (0..8).map(|_| {
+        let sig = sig.clone();
+        let counter = counter.clone();
+        thread::spawn(move || {
+            let mut kept = vec![];
+            for i in 0..100 {
+                let c = counter.clone();
+                let sub = sig.subscribe("signal", Box::new(move |_sig| {
+                    *c.lock().unwrap() += 1;
+                })).unwrap();
+                // half of the subscriptions are dropped while the other
+                // threads subscribe
+                match i % 2 {
+                    0 => kept.push(sub.detach()),
+                    _ => drop(sub),
+                }
+            }
+            kept
+        })
+    }).collect();
+
+    let mut ids = vec![];
+    for t in threads {
+        ids.extend(t.join().unwrap());
+    }
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 400);
+    assert_eq!(sig.status().subscriptions, 400);
+
+    sig.emit(SigType::Update, "signal:1").unwrap();
+    sig.stop();
+    assert_eq!(*counter.lock().unwrap(), 400);
+}
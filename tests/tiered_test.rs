@@ -0,0 +1,224 @@
+use anyhow::Error;
+
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::Limits;
+use mdl::Policy;
+use mdl::ReadOnly;
+use mdl::Store;
+use mdl::TieredStore;
+use mdl::WriteMode;
+
+use std::cell::RefCell;
+use std::fs::remove_dir_all;
+
+static DB: &str = "/tmp/test.lmdb";
+
+fn keys<S: Store>(store: &S, db: &'static str, prefix: &str) -> Vec<String> {
+    let keys = RefCell::new(vec![]);
+    store.iter_kv(db, prefix, |k, _| {
+        keys.borrow_mut().push(k.to_string());
+        Continue(true)
+    }).unwrap_or(());
+    keys.into_inner()
+}
+
+fn value<S: Store>(store: &S, key: &str) -> Option<Vec<u8>> {
+    store.pull("db", key, |data| Ok(data.to_vec())).ok()
+}
+
+#[test]
+fn write_through_test() {
+    let path = &format!("{}-tiered", DB);
+    let _ = remove_dir_all(path);
+    let store = TieredStore::new(BCache::new().unwrap(), Cache::new(path).unwrap(),
+                                 WriteMode::Through);
+
+    store.push("db", "a", vec![1]).unwrap();
+    assert_eq!(value(store.front(), "a"), Some(vec![1]));
+    assert_eq!(value(store.back(), "a"), Some(vec![1]));
+
+    // reads fall back to the back store and fill the front store
+    store.invalidate("db", "a");
+    assert_eq!(value(store.front(), "a"), None);
+    assert_eq!(value(&store, "a"), Some(vec![1]));
+    assert_eq!(value(store.front(), "a"), Some(vec![1]));
+
+    store.rm("db", "a").unwrap();
+    assert_eq!(value(&store, "a"), None);
+    assert_eq!(value(store.front(), "a"), None);
+    assert_eq!(value(store.back(), "a"), None);
+    assert!(store.rm("db", "a").is_err());
+    let _ = remove_dir_all(path);
+}
+
+#[test]
+fn write_behind_test() {
+    let store = TieredStore::new(BCache::new().unwrap(), BCache::new().unwrap(),
+                                 WriteMode::Behind { max_pending: 3 });
+
+    store.push("db", "a", vec![1]).unwrap();
+    store.push("db", "b", vec![2]).unwrap();
+    assert_eq!(store.pending(), 2);
+    assert_eq!(value(&store, "a"), Some(vec![1]));
+    assert_eq!(value(store.back(), "a"), None);
+
+    store.flush().unwrap();
+    assert_eq!(store.pending(), 0);
+    assert_eq!(value(store.back(), "a"), Some(vec![1]));
+
+    store.rm("db", "a").unwrap();
+    assert_eq!(value(&store, "a"), None);
+    assert_eq!(value(store.back(), "a"), Some(vec![1]));
+    assert!(store.rm("db", "a").is_err());
+
+    // the third pending write flushes
+    store.push("db", "c", vec![3]).unwrap();
+    store.push("db", "d", vec![4]).unwrap();
+    assert_eq!(store.pending(), 0);
+    assert_eq!(value(store.back(), "a"), None);
+    assert_eq!(value(store.back(), "d"), Some(vec![4]));
+}
+
+#[test]
+fn evicted_pending_test() {
+    // the pending writes are read even if the front store evicts them
+    let front = BCache::with_limits(Limits::entries(1, Policy::Lru)).unwrap();
+    let store = TieredStore::new(front, BCache::new().unwrap(),
+                                 WriteMode::Behind { max_pending: 100 });
+    store.push("db", "a", vec![1]).unwrap();
+    store.push("db", "b", vec![2]).unwrap();
+    assert_eq!(value(store.front(), "a"), None);
+    assert_eq!(value(&store, "a"), Some(vec![1]));
+}
+
+#[test]
+fn merged_iter_test() {
+    let path = &format!("{}-tiered-iter", DB);
+    let _ = remove_dir_all(path);
+    let store = TieredStore::new(BCache::new().unwrap(), Cache::new(path).unwrap(),
+                                 WriteMode::Behind { max_pending: 100 });
+
+    // empty back store
+    store.push("db", "k:3", vec![3]).unwrap();
+    assert_eq!(keys(&store, "db", "k:"), vec!["k:3"]);
+
+    for k in &["k:1", "k:2", "k:4", "k:6", "other"] {
+        store.push("db", k, vec![0]).unwrap();
+    }
+    store.flush().unwrap();
+
+    store.push("db", "k:0", vec![0]).unwrap();
+    store.push("db", "k:5", vec![5]).unwrap();
+    store.push("db", "k:7", vec![7]).unwrap();
+    store.push("db", "k:2", vec![22]).unwrap();
+    store.rm("db", "k:4").unwrap();
+    store.push("other-db", "k:8", vec![8]).unwrap();
+
+    assert_eq!(keys(&store, "db", "k:"),
+               vec!["k:0", "k:1", "k:2", "k:3", "k:5", "k:6", "k:7"]);
+    let values = store.all("db", "k:2", |data| Ok(data.to_vec())).unwrap();
+    assert_eq!(values, vec![vec![22]]);
+
+    // stops in the pending writes and in the back store keys
+    let n = RefCell::new(0);
+    store.iter("db", "k:", |_| {
+        *n.borrow_mut() += 1;
+        Continue(*n.borrow() < 3)
+    }).unwrap();
+    assert_eq!(*n.borrow(), 3);
+
+    store.flush().unwrap();
+    assert_eq!(keys(store.back(), "db", "k:"),
+               vec!["k:0", "k:1", "k:2", "k:3", "k:5", "k:6", "k:7"]);
+    let _ = remove_dir_all(path);
+}
+
+/// Store without `iter_kv`
+struct NoIter(BCache);
+
+impl Store for NoIter {
+    fn push(&self, db: &'static str, key: &str, value: Vec<u8>)
+        -> Result<(), Error> {
+        self.0.push(db, key, value)
+    }
+
+    fn pull<F, T>(&self, db: &'static str, key: &str, formatter: F)
+        -> Result<T, Error>
+        where F: Fn(&[u8]) -> Result<T, Error> {
+        self.0.pull(db, key, formatter)
+    }
+
+    fn iter<F>(&self, db: &'static str, prefix: &str, f: F)
+        -> Result<(), Error>
+        where F: Fn(&[u8]) -> Continue {
+        self.0.iter(db, prefix, f)
+    }
+
+    fn rm(&self, db: &'static str, key: &str) -> Result<(), Error> {
+        self.0.rm(db, key)
+    }
+}
+
+#[test]
+fn back_errors_test() {
+    // the back store errors are returned, also with pending writes
+    let store = TieredStore::new(BCache::new().unwrap(), NoIter(BCache::new().unwrap()),
+                                 WriteMode::Behind { max_pending: 100 });
+    assert!(store.iter_kv("db", "", |_, _| Continue(true)).is_err());
+    store.push("db", "a", vec![1]).unwrap();
+    assert!(store.iter_kv("db", "", |_, _| Continue(true)).is_err());
+
+    // pending writes without back store keys, LMDB fails with not found
+    let path = &format!("{}-tiered-errors", DB);
+    let _ = remove_dir_all(path);
+    let store = TieredStore::new(BCache::new().unwrap(), Cache::new(path).unwrap(),
+                                 WriteMode::Behind { max_pending: 100 });
+    store.push("db", "a", vec![1]).unwrap();
+    assert_eq!(keys(&store, "db", ""), vec!["a"]);
+    let _ = remove_dir_all(path);
+}
+
+#[test]
+fn front_errors_test() {
+    // the value is read even if it can't be copied to the front store
+    let back = BCache::new().unwrap();
+    back.push("db", "a", vec![1]).unwrap();
+    let store = TieredStore::new(ReadOnly::new(BCache::new().unwrap()), back, WriteMode::Through);
+    assert_eq!(value(&store, "a"), Some(vec![1]));
+}
+
+#[test]
+fn flush_errors_test() {
+    // the writes stay pending until they're in the back store
+    let back = BCache::new().unwrap();
+    back.push("db", "a", vec![1]).unwrap();
+    let store = TieredStore::new(BCache::new().unwrap(), ReadOnly::new(back),
+                                 WriteMode::Behind { max_pending: 100 });
+    store.rm("db", "a").unwrap();
+    store.push("db", "b", vec![2]).unwrap();
+    assert!(store.flush().is_err());
+    assert_eq!(store.pending(), 2);
+    assert_eq!(value(&store, "a"), None);
+    assert_eq!(value(&store, "b"), Some(vec![2]));
+
+    // the keys removed before reaching the back store
+    let store = TieredStore::new(BCache::new().unwrap(), BCache::new().unwrap(),
+                                 WriteMode::Behind { max_pending: 100 });
+    store.push("db", "c", vec![3]).unwrap();
+    store.rm("db", "c").unwrap();
+    store.flush().unwrap();
+    assert_eq!(store.pending(), 0);
+    assert_eq!(value(store.back(), "c"), None);
+}
+
+#[test]
+fn front_push_errors_test() {
+    // the value is in the back store even if it can't be cached
+    let store = TieredStore::new(ReadOnly::new(BCache::new().unwrap()), BCache::new().unwrap(),
+                                 WriteMode::Through);
+    store.push("db", "a", vec![1]).unwrap();
+    assert_eq!(value(store.back(), "a"), Some(vec![1]));
+    assert_eq!(value(&store, "a"), Some(vec![1]));
+}
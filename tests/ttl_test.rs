@@ -0,0 +1,205 @@
+use mdl::BCache;
+use mdl::Cache;
+use mdl::Continue;
+use mdl::Model;
+use mdl::Pattern;
+use mdl::SigType;
+use mdl::SignalerAsync;
+use mdl::Store;
+use mdl::Sweeper;
+use mdl::ttl;
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::fs::remove_dir_all;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+static DB: &str = "/tmp/test.lmdb";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Response {
+    pub url: String,
+}
+impl Model for Response {
+    fn key(&self) -> String {
+        format!("response:{}", self.url)
+    }
+
+    // no default ttl, but stored with store_with_ttl
+    fn expires() -> bool { true }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Page {
+    pub id: u32,
+}
+impl Model for Page {
+    fn key(&self) -> String {
+        format!("page:{}", self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Session {
+    pub id: u32,
+}
+impl Model for Session {
+    fn key(&self) -> String {
+        format!("session:{}", self.id)
+    }
+
+    fn db() -> &'static str { "sessions" }
+
+    fn ttl() -> Option<Duration> { Some(Duration::from_millis(50)) }
+}
+
+fn response(url: &str) -> Response {
+    Response { url: url.to_string() }
+}
+
+fn expiry<S: Store>(store: &S) {
+    let short = Duration::from_millis(50);
+    response("a").store_with_ttl(store, short).unwrap();
+    response("b").store_with_ttl(store, Duration::from_secs(60)).unwrap();
+    response("c").store(store).unwrap();
+    // storing again without ttl, the object doesn't expire
+    response("d").store_with_ttl(store, short).unwrap();
+    response("d").store(store).unwrap();
+
+    assert!(Response::get(store, "response:a").is_ok());
+    assert_eq!(Response::all(store, "response").unwrap().len(), 4);
+
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(Response::get(store, "response:a").is_err());
+    assert!(Response::get(store, "response:b").is_ok());
+    assert_eq!(Response::all(store, "response").unwrap().len(), 3);
+
+    let urls = RefCell::new(vec![]);
+    Response::iter(store, "response", |r| {
+        urls.borrow_mut().push(r.url);
+        Continue(true)
+    }).unwrap();
+    assert_eq!(*urls.borrow(), vec!["b", "c", "d"]);
+
+    // the expired object is still in the store until it's purged
+    assert!(store.pull(Response::db(), "response:a", |_| Ok(())).is_ok());
+    let signals = ttl::purge(store, Response::db(), 10).unwrap();
+    assert_eq!(signals.len(), 1);
+    assert_eq!(signals[0].name, "response:a");
+    assert!(signals[0].old.is_some());
+    assert!(store.pull(Response::db(), "response:a", |_| Ok(())).is_err());
+    assert!(ttl::purge(store, Response::db(), 10).unwrap().is_empty());
+}
+
+#[test]
+fn cache_expiry_test() {
+    let db = &format!("{}-ttl", DB);
+    let _ = remove_dir_all(db);
+    let cache = Cache::new(db).unwrap();
+    expiry(&cache);
+    let _ = remove_dir_all(db);
+}
+
+#[test]
+fn bcache_expiry_test() {
+    let cache = BCache::new().unwrap();
+    expiry(&cache);
+}
+
+#[test]
+fn ttl_hook_test() {
+    let cache = BCache::new().unwrap();
+    Session { id: 1 }.store(&cache).unwrap();
+    assert!(Session::get(&cache, "session:1").is_ok());
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(Session::get(&cache, "session:1").is_err());
+}
+
+#[test]
+fn no_ttl_test() {
+    let db = &format!("{}-nottl", DB);
+    let _ = remove_dir_all(db);
+    let cache = Cache::new(db).unwrap();
+
+    let page = Page { id: 1 };
+    page.store(&cache).unwrap();
+    assert!(Page::get(&cache, "page:1").is_ok());
+    assert_eq!(Page::all(&cache, "page").unwrap().len(), 1);
+    page.delete(&cache).unwrap();
+    assert!(page.store_with_ttl(&cache, Duration::from_secs(1)).is_err());
+
+    // the models that can't expire don't use the ttl db
+    assert!(!cache.dbs().unwrap().contains(&ttl::TTL_DB.to_string()));
+    let _ = remove_dir_all(db);
+}
+
+#[test]
+fn ttl_key_test() {
+    let cache = BCache::new().unwrap();
+    ttl::expire(&cache, "a", "b:x", Duration::from_millis(0)).unwrap();
+    ttl::expire(&cache, "a:b", "x", Duration::from_secs(60)).unwrap();
+
+    assert!(ttl::is_expired(&cache, "a", "b:x"));
+    assert!(!ttl::is_expired(&cache, "a:b", "x"));
+    assert!(ttl::expired(&cache, "a:b", "").is_empty());
+    assert_eq!(ttl::expired(&cache, "a", "").len(), 1);
+}
+
+#[test]
+fn long_ttl_test() {
+    let cache = BCache::new().unwrap();
+    ttl::expire(&cache, "db", "a", Duration::MAX).unwrap();
+    ttl::expire(&cache, "db", "b", Duration::from_millis(u64::MAX)).unwrap();
+    assert!(!ttl::is_expired(&cache, "db", "a"));
+    assert!(!ttl::is_expired(&cache, "db", "b"));
+    assert!(ttl::expired(&cache, "db", "").is_empty());
+}
+
+#[test]
+fn purge_batch_test() {
+    let cache = BCache::new().unwrap();
+    for i in 0..25 {
+        Session { id: i }.store(&cache).unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(ttl::purge(&cache, Session::db(), 10).unwrap().len(), 10);
+    assert_eq!(ttl::purge(&cache, Session::db(), 10).unwrap().len(), 10);
+    assert_eq!(ttl::purge(&cache, Session::db(), 10).unwrap().len(), 5);
+}
+
+#[test]
+fn sweeper_test() {
+    let db = &format!("{}-sweeper", DB);
+    let _ = remove_dir_all(db);
+    let cache = Arc::new(Cache::new(db).unwrap());
+    let sig = SignalerAsync::new();
+    sig.signal_loop();
+
+    let deleted = Arc::new(Mutex::new(vec![]));
+    let d = deleted.clone();
+    let _sub = sig.subscribe(Pattern::glob("session:*"), Box::new(move |s| {
+        if let SigType::Delete = s.type_ {
+            d.lock().unwrap().push(s.name);
+        }
+    })).unwrap();
+
+    for i in 0..5 {
+        Session { id: i }.store(&cache).unwrap();
+    }
+
+    let sweeper = Sweeper::start_sig(cache.clone(), sig.clone(), &[Session::db()],
+                                     Duration::from_millis(20), 2).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    sweeper.stop();
+    sig.stop();
+
+    assert_eq!(deleted.lock().unwrap().len(), 5);
+    assert!(cache.pull(Session::db(), "session:1", |_| Ok(())).is_err());
+    let _ = remove_dir_all(db);
+}